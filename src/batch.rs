@@ -0,0 +1,223 @@
+use crate::language::Language;
+use crate::lsp_message;
+use crate::path_filter::FileFilter;
+use crate::qualified_name::QualifiedName;
+use crate::result_handler::{self, LspResult, QueryTiming, ResultFilters};
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// One line of a batch, a query with the kinds and files it is limited to when given
+#[derive(PartialEq, Debug)]
+pub struct BatchQuery {
+    pub query: String,
+    pub kinds: Option<Vec<String>>,
+    pub files: Option<Vec<String>>,
+}
+
+impl BatchQuery {
+    // e.g. "parse --kind function,method --file src/net", None for blank lines and # comments
+    pub fn parse(line: &str) -> Result<Option<BatchQuery>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let mut query = None;
+        let mut kinds: Option<Vec<String>> = None;
+        let mut files: Option<Vec<String>> = None;
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            match word {
+                "--kind" | "-k" => {
+                    let value = words
+                        .next()
+                        .ok_or("--kind needs kinds, e.g. --kind function,method")?;
+                    kinds.get_or_insert_with(Vec::new).extend(
+                        value
+                            .split(',')
+                            .filter(|kind| !kind.is_empty())
+                            .map(|kind| kind.to_string()),
+                    );
+                }
+                "--file" | "-f" => {
+                    let value = words
+                        .next()
+                        .ok_or("--file needs a file, directory or glob")?;
+                    files.get_or_insert_with(Vec::new).push(value.to_string());
+                }
+                _ if word.starts_with('-') => {
+                    return Err(format!(
+                        "Unknown option '{}', only --kind and --file can follow a query",
+                        word
+                    ))
+                }
+                _ if query.is_some() => {
+                    return Err(format!("Expected one query per line, found '{}' too", word))
+                }
+                _ => query = Some(word.to_string()),
+            }
+        }
+
+        match query {
+            Some(query) => Ok(Some(BatchQuery {
+                query,
+                kinds,
+                files,
+            })),
+            None => Err("Missing the query before --kind or --file".to_string()),
+        }
+    }
+}
+
+// Every query of the input, failing on the first line that can't be read
+pub fn read_queries(input: &mut dyn BufRead) -> Result<Vec<BatchQuery>, String> {
+    let mut queries = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        match BatchQuery::parse(&line) {
+            Ok(Some(query)) => queries.push(query),
+            Ok(None) => {}
+            Err(e) => return Err(format!("line {}: {}", number + 1, e)),
+        }
+    }
+    Ok(queries)
+}
+
+fn get_invalid_error(query: &BatchQuery, error: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} (query '{}')", error, query.query),
+    )
+}
+
+// Starts the server once and asks it every query in turn, each result tagged with its query
+pub fn run(
+    language: Language,
+    root: &Path,
+    queries: &[BatchQuery],
+    mut filters: ResultFilters,
+) -> io::Result<(Vec<LspResult>, QueryTiming)> {
+    // check every query's filters before paying for the server
+    let mut prepared = Vec::new();
+    for query in queries {
+        let kinds = match &query.kinds {
+            Some(kinds) => Some(
+                filters
+                    .kinds
+                    .with_include(kinds)
+                    .map_err(|e| get_invalid_error(query, e))?,
+            ),
+            None => None,
+        };
+        let files = match &query.files {
+            Some(files) => Some(
+                FileFilter::new(root, files)
+                    .map_err(|e| get_invalid_error(query, format!("Invalid --file glob: {}", e)))?,
+            ),
+            None => None,
+        };
+        prepared.push((query, kinds, files));
+    }
+
+    let (mut writer, mut reader, server_instance, mut indexing) =
+        crate::connect_server(language, root);
+    writer.write_all(lsp_message::init_request(&root.to_string_lossy()).as_bytes())?;
+    crate::notify_initialized(writer.as_mut());
+    let rls_stdin = writer.as_mut();
+    let lock = reader.as_mut();
+
+    let mut results = Vec::new();
+    let mut timing = QueryTiming {
+        server: Duration::default(),
+        requests: 0,
+        resolve: Duration::default(),
+    };
+    for (query, kinds, files) in prepared {
+        let name = QualifiedName::parse(&query.query, language, root);
+        filters.qualifier = name.qualifier;
+        // a query's own kinds and files stand in for the command line's while it runs, the
+        // command line's --exclude-kind still applies
+        let kinds = kinds.map(|kinds| mem::replace(&mut filters.kinds, kinds));
+        let files = files.map(|files| mem::replace(&mut filters.files, files));
+
+        let server_start = Instant::now();
+        let (response, requests) =
+            crate::get_symbol_response_or_timeout(&name.name, &mut indexing, rls_stdin, lock);
+        timing.server += server_start.elapsed();
+        timing.requests += requests;
+
+        let resolve_start = Instant::now();
+        let found =
+            result_handler::collect_results(&response, &name.name, &filters, rls_stdin, lock)?;
+        results.extend(
+            found
+                .into_iter()
+                .map(|result| result.with_query(&query.query)),
+        );
+        timing.resolve += resolve_start.elapsed();
+
+        if let Some(kinds) = kinds {
+            filters.kinds = kinds;
+        }
+        if let Some(files) = files {
+            filters.files = files;
+        }
+    }
+
+    crate::stop_server(server_instance);
+    Ok((results, timing))
+}
+
+#[cfg(test)]
+mod batch_tests {
+
+    use super::*;
+
+    #[test]
+    fn query_lines_take_kinds_and_files() {
+        assert_eq!(
+            Some(BatchQuery {
+                query: "Client::send".to_string(),
+                kinds: Some(vec!["function".to_string(), "method".to_string()]),
+                files: Some(vec!["src/net".to_string(), "src/*.rs".to_string()]),
+            }),
+            BatchQuery::parse("Client::send -k function,method --file src/net -f src/*.rs")
+                .unwrap()
+        );
+        assert_eq!(
+            Some(BatchQuery {
+                query: "parse".to_string(),
+                kinds: None,
+                files: None,
+            }),
+            BatchQuery::parse("  parse ").unwrap()
+        );
+    }
+
+    #[test]
+    fn bad_lines_are_reported_by_number() {
+        let mut input = "# audit\nparse\n\n--kind struct\n".as_bytes();
+        assert_eq!(
+            Err("line 4: Missing the query before --kind or --file".to_string()),
+            read_queries(&mut input)
+        );
+        assert!(BatchQuery::parse("parse --kind").is_err());
+        assert!(BatchQuery::parse("parse send").is_err());
+        assert!(BatchQuery::parse("parse --all").is_err());
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let mut input = "# audit\nparse\n\nsend --kind method\n".as_bytes();
+        let queries = read_queries(&mut input).unwrap();
+        assert_eq!(
+            vec!["parse", "send"],
+            queries
+                .iter()
+                .map(|query| query.query.as_str())
+                .collect::<Vec<&str>>()
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use crate::position;
+use crate::source_context::SymbolRange;
+use colored::Color;
+use json::JsonValue;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Change {
+    Added,
+    Modified,
+    Removed,
+}
+
+impl Change {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Modified => "modified",
+            Change::Removed => "removed",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Change::Added => Color::Green,
+            Change::Modified => Color::Yellow,
+            Change::Removed => Color::Red,
+        }
+    }
+}
+
+// Lines are 0-based like the server's, both ends inclusive
+#[derive(Default)]
+pub struct FileDiff {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub removed: Vec<(u32, u32)>,
+    pub added: Vec<(u32, u32)>,
+}
+
+// Where the text on either side of the diff comes from
+#[derive(Clone, PartialEq, Debug)]
+pub enum Side {
+    Rev(String),
+    WorkTree,
+}
+
+fn run_git(root: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").current_dir(root).args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Paths in a diff are relative to the top of the repository, wherever glsp was run from
+pub fn get_repo_root(cwd: &Path) -> io::Result<PathBuf> {
+    let root = run_git(cwd, &["rev-parse", "--show-toplevel"])?;
+    Ok(PathBuf::from(root.trim()))
+}
+
+fn or_head(rev: &str) -> String {
+    if rev.is_empty() {
+        "HEAD".to_string()
+    } else {
+        rev.to_string()
+    }
+}
+
+// Follows git diff's reading of a range: "A...B" compares B with the merge base,
+// "A..B" compares the two revisions and a single revision is compared with the work tree
+pub fn get_sides(root: &Path, range: Option<&str>) -> io::Result<(Side, Side)> {
+    let range = match range {
+        Some(range) => range,
+        None => return Ok((Side::Rev("HEAD".to_string()), Side::WorkTree)),
+    };
+
+    if let Some((from, to)) = range.split_once("...") {
+        let base = run_git(root, &["merge-base", &or_head(from), &or_head(to)])?;
+        Ok((Side::Rev(base.trim().to_string()), Side::Rev(or_head(to))))
+    } else if let Some((from, to)) = range.split_once("..") {
+        Ok((Side::Rev(or_head(from)), Side::Rev(or_head(to))))
+    } else {
+        Ok((Side::Rev(range.to_string()), Side::WorkTree))
+    }
+}
+
+pub fn get_diff(root: &Path, range: Option<&str>) -> io::Result<String> {
+    let mut args = vec![
+        "diff",
+        "--unified=0",
+        "--no-color",
+        "--no-ext-diff",
+        "--no-renames",
+    ];
+    args.push(range.unwrap_or("HEAD"));
+    run_git(root, &args)
+}
+
+// Reads a file as it was on one side of the diff, paths are relative to the repository root
+pub fn read_side(root: &Path, side: &Side, path: &str) -> Option<String> {
+    match side {
+        Side::Rev(rev) => run_git(root, &["show", &format!("{}:{}", rev, path)]).ok(),
+        Side::WorkTree => std::fs::read_to_string(root.join(path)).ok(),
+    }
+}
+
+// Files git doesn't track yet are missing from its diff, though they are new in the work tree
+pub fn get_untracked(root: &Path, extensions: &[&str]) -> io::Result<Vec<FileDiff>> {
+    let files = run_git(root, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+    Ok(parse_untracked(&files, extensions))
+}
+
+// Every line of an untracked file counts as added
+fn parse_untracked(files: &str, extensions: &[&str]) -> Vec<FileDiff> {
+    files
+        .split('\0')
+        .filter(|path| {
+            Path::new(path)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| extensions.contains(&extension))
+        })
+        .map(|path| FileDiff {
+            new_path: Some(path.to_string()),
+            added: vec![(0, u32::MAX)],
+            ..FileDiff::default()
+        })
+        .collect()
+}
+
+fn get_diff_path(line: &str, prefix: &str) -> Option<String> {
+    let path = line.trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(path.strip_prefix(prefix).unwrap_or(path).to_string())
+}
+
+// "-12,3" or "+40" from a hunk header, a missing count means one line
+fn parse_hunk_side(side: &str) -> Option<(u32, u32)> {
+    let mut parts = side[1..].splitn(2, ',');
+    let start: u32 = parts.next()?.parse().ok()?;
+    let count: u32 = match parts.next() {
+        Some(count) => count.parse().ok()?,
+        None => 1,
+    };
+
+    if count == 0 {
+        return None;
+    }
+    Some((start - 1, start - 1 + count - 1))
+}
+
+pub fn parse_diff(text: &str) -> Vec<FileDiff> {
+    let mut diffs: Vec<FileDiff> = Vec::new();
+
+    for line in text.lines() {
+        if line.starts_with("diff --git ") {
+            diffs.push(FileDiff::default());
+            continue;
+        }
+        let diff = match diffs.last_mut() {
+            Some(diff) => diff,
+            None => continue,
+        };
+
+        if let Some(path) = line.strip_prefix("--- ") {
+            diff.old_path = get_diff_path(path, "a/");
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            diff.new_path = get_diff_path(path, "b/");
+        } else if line.starts_with("@@ ") {
+            let mut sides = line.split(' ').skip(1);
+            if let Some(removed) = sides.next().and_then(parse_hunk_side) {
+                diff.removed.push(removed);
+            }
+            if let Some(added) = sides.next().and_then(parse_hunk_side) {
+                diff.added.push(added);
+            }
+        }
+    }
+
+    // binary files and mode changes have no hunks to look at
+    diffs
+        .into_iter()
+        .filter(|diff| !diff.removed.is_empty() || !diff.added.is_empty())
+        .collect()
+}
+
+fn intersects(range: SymbolRange, lines: &[(u32, u32)]) -> bool {
+    lines
+        .iter()
+        .any(|(start, end)| range.start_line <= *end && range.end_line >= *start)
+}
+
+// Symbols are matched across the two sides by what they are called and where they live,
+// since their lines move whenever anything above them changes
+fn get_key(symbol: &JsonValue) -> (String, String, String) {
+    (
+        symbol["name"].to_string(),
+        symbol["kind"].to_string(),
+        symbol["containerName"].as_str().unwrap_or("").to_string(),
+    )
+}
+
+fn get_symbol_range(symbol: &JsonValue) -> SymbolRange {
+    position::get_range(&symbol["location"]["range"])
+}
+
+// A changed symbol that exists on both sides was modified, one only on the new side was
+// added and one only on the old side was removed
+pub fn classify(
+    old_symbols: &[JsonValue],
+    new_symbols: &[JsonValue],
+    diff: &FileDiff,
+) -> Vec<(JsonValue, Change)> {
+    let mut changes: Vec<(JsonValue, Change)> = Vec::new();
+    let mut modified: Vec<(String, String, String)> = Vec::new();
+
+    for symbol in new_symbols {
+        if !intersects(get_symbol_range(symbol), &diff.added) {
+            continue;
+        }
+        let key = get_key(symbol);
+        if old_symbols.iter().any(|old| get_key(old) == key) {
+            modified.push(key);
+            changes.push((symbol.clone(), Change::Modified));
+        } else {
+            changes.push((symbol.clone(), Change::Added));
+        }
+    }
+
+    for symbol in old_symbols {
+        if !intersects(get_symbol_range(symbol), &diff.removed) {
+            continue;
+        }
+        let key = get_key(symbol);
+        match new_symbols.iter().find(|new| get_key(new) == key) {
+            // lines were only taken out of it, report where it is now
+            Some(new) if !modified.contains(&key) => {
+                modified.push(key);
+                changes.push((new.clone(), Change::Modified));
+            }
+            Some(_) => continue,
+            None => changes.push((symbol.clone(), Change::Removed)),
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod changed_tests {
+
+    use super::*;
+    use json::object;
+
+    const DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -3 +3,2 @@ pub struct Parser {
+-    input: String,
++    input: String,
++    offset: usize,
+@@ -20,2 +21,0 @@ impl Parser {
+diff --git a/src/old.rs b/src/old.rs
+deleted file mode 100644
+--- a/src/old.rs
++++ /dev/null
+@@ -1,4 +0,0 @@
+diff --git a/logo.png b/logo.png
+Binary files a/logo.png and b/logo.png differ
+";
+
+    #[test]
+    fn hunks_are_parsed_to_zero_based_lines() {
+        let diffs = parse_diff(DIFF);
+        assert_eq!(2, diffs.len());
+
+        assert_eq!(Some("src/lib.rs".to_string()), diffs[0].new_path);
+        assert_eq!(vec![(2, 2), (19, 20)], diffs[0].removed);
+        assert_eq!(vec![(2, 3)], diffs[0].added);
+
+        assert_eq!(Some("src/old.rs".to_string()), diffs[1].old_path);
+        assert_eq!(None, diffs[1].new_path);
+        assert!(diffs[1].added.is_empty());
+    }
+
+    #[test]
+    fn untracked_source_files_are_all_added() {
+        let diffs = parse_untracked("src/new.rs\0notes.txt\0src/net/mod.rs\0", &["rs"]);
+        assert_eq!(2, diffs.len());
+        assert_eq!(None, diffs[0].old_path);
+        assert_eq!(Some("src/new.rs".to_string()), diffs[0].new_path);
+        assert_eq!(vec![(0, u32::MAX)], diffs[0].added);
+        assert_eq!(Some("src/net/mod.rs".to_string()), diffs[1].new_path);
+    }
+
+    #[test]
+    fn single_revision_is_compared_with_work_tree() {
+        let (old, new) = get_sides(Path::new("."), Some("v1.0")).unwrap();
+        assert_eq!(Side::Rev("v1.0".to_string()), old);
+        assert_eq!(Side::WorkTree, new);
+
+        let (old, new) = get_sides(Path::new("."), Some("main..")).unwrap();
+        assert_eq!(Side::Rev("main".to_string()), old);
+        assert_eq!(Side::Rev("HEAD".to_string()), new);
+    }
+
+    fn get_symbol(name: &str, start: u32, end: u32) -> JsonValue {
+        object! {
+            "name" => name,
+            "kind" => 12,
+            "location" => object!{
+                "uri" => "file:///p/src/lib.rs",
+                "range" => object!{
+                    "start" => object!{ "line" => start, "character" => 0 },
+                    "end" => object!{ "line" => end, "character" => 1 }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn symbols_are_classified_by_side() {
+        let old = vec![get_symbol("parse", 0, 5), get_symbol("lex", 10, 14)];
+        let new = vec![get_symbol("parse", 0, 6), get_symbol("tokens", 8, 9)];
+        let diff = FileDiff {
+            old_path: Some("src/lib.rs".to_string()),
+            new_path: Some("src/lib.rs".to_string()),
+            removed: vec![(10, 14)],
+            added: vec![(3, 3), (8, 9)],
+        };
+
+        let changes: Vec<(String, Change)> = classify(&old, &new, &diff)
+            .into_iter()
+            .map(|(symbol, change)| (symbol["name"].to_string(), change))
+            .collect();
+        assert_eq!(
+            vec![
+                ("parse".to_string(), Change::Modified),
+                ("tokens".to_string(), Change::Added),
+                ("lex".to_string(), Change::Removed),
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn symbol_with_only_removed_lines_is_modified() {
+        let old = vec![get_symbol("parse", 0, 8)];
+        let new = vec![get_symbol("parse", 0, 5)];
+        let diff = FileDiff {
+            removed: vec![(4, 6)],
+            ..FileDiff::default()
+        };
+
+        let changes = classify(&old, &new, &diff);
+        assert_eq!(1, changes.len());
+        assert_eq!(Change::Modified, changes[0].1);
+        assert_eq!(5, changes[0].0["location"]["range"]["end"]["line"]);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::ignore_filter::IgnoreFilter;
+use crate::language::{self, Language};
+use crate::lsp_message;
+use crate::path_filter::{FileFilter, PathFilter};
+use crate::paths;
+use crate::qualified_name::QualifiedName;
+use crate::result_handler::{self, DeprecatedFilter, KindFilter, LspResult, ResultFilters};
+use json::JsonValue;
+use std::fmt;
+use std::io::{self, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long a server may take to exit once asked to before it is killed
+const EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+// How long spawn waits for the server to answer initialize
+pub const INIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// A symbol found by a Client, with lines and characters counted from 0 like the server does
+#[derive(Clone, PartialEq)]
+pub struct SymbolMatch(LspResult);
+
+impl SymbolMatch {
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    // e.g. "Function" or "Struct", "Reference" for the results of Client::references
+    pub fn kind(&self) -> &str {
+        self.0.kind()
+    }
+
+    // The type the server reports for variables, the kind for everything else
+    pub fn detail(&self) -> &str {
+        self.0.data_type()
+    }
+
+    // Empty for top level symbols
+    pub fn container(&self) -> &str {
+        self.0.container()
+    }
+
+    pub fn path(&self) -> PathBuf {
+        paths::uri_to_path(self.0.location())
+    }
+
+    pub fn line(&self) -> u32 {
+        self.0.range().start_line
+    }
+
+    pub fn character(&self) -> u32 {
+        self.0.range().start_character
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        self.0.is_deprecated()
+    }
+}
+
+impl fmt::Debug for SymbolMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SymbolMatch")
+            .field("name", &self.name())
+            .field("kind", &self.kind())
+            .field("container", &self.container())
+            .field("path", &self.path())
+            .field("line", &self.line())
+            .field("character", &self.character())
+            .finish()
+    }
+}
+
+// Hover contents come as markup, a marked string or a list of them, only the first is kept
+fn get_hover_text(contents: &JsonValue) -> Option<String> {
+    let first = if contents.is_array() {
+        &contents[0]
+    } else {
+        contents
+    };
+    let text = if first.is_object() {
+        first["value"].as_str()
+    } else {
+        first.as_str()
+    };
+    text.map(|text| text.to_string())
+}
+
+// A language server started for one project, asked one request at a time. The server is
+// killed when the client is dropped without a shutdown
+pub struct Client {
+    language: Language,
+    root: PathBuf,
+    server: Child,
+    writer: ChildStdin,
+    reader: BufReader<ChildStdout>,
+    // as on the command line, dependencies and ignored files are left out
+    filters: ResultFilters,
+    indexing: lsp_message::Indexing,
+}
+
+impl Client {
+    // Starts the language's server in the project root and waits up to INIT_TIMEOUT for it to
+    // initialize
+    pub fn spawn(language: Language, root: &Path) -> io::Result<Client> {
+        Client::spawn_with_timeout(language, root, INIT_TIMEOUT)
+    }
+
+    // Fails with TimedOut when the server doesn't answer initialize within the timeout
+    pub fn spawn_with_timeout(
+        language: Language,
+        root: &Path,
+        timeout: Duration,
+    ) -> io::Result<Client> {
+        let excludes: Vec<String> = language
+            .default_excludes()
+            .iter()
+            .map(|exclude| exclude.to_string())
+            .collect();
+        let filters = ResultFilters {
+            files: FileFilter::new(root, &[]).map_err(io::Error::other)?,
+            kinds: KindFilter::new(Vec::new(), Vec::new()),
+            containers: Vec::new(),
+            paths: PathFilter::new(root, &[], &excludes).map_err(io::Error::other)?,
+            ignore: Some(IgnoreFilter::new(root)),
+            qualifier: None,
+            deprecated: DeprecatedFilter::Any,
+            lines: None,
+        };
+
+        let mut server = language::run_server(language, root)?;
+        let (writer, reader) = match (server.stdin.take(), server.stdout.take()) {
+            (Some(writer), Some(reader)) => (writer, BufReader::new(reader)),
+            _ => return Err(io::Error::other("the server's stdin or stdout is missing")),
+        };
+        let mut client = Client {
+            language,
+            root: root.to_path_buf(),
+            server,
+            writer,
+            reader,
+            filters,
+            indexing: lsp_message::Indexing::new(lsp_message::INDEXING_TIMEOUT),
+        };
+
+        client
+            .writer
+            .write_all(lsp_message::init_request(&root.to_string_lossy()).as_bytes())?;
+        client.read_response(lsp_message::INIT_REQUEST_ID, Some(Instant::now() + timeout))?;
+        client
+            .writer
+            .write_all(lsp_message::init_notification().as_bytes())?;
+        Ok(client)
+    }
+
+    // Waits until the server has written something, failing once the deadline has passed
+    fn wait_for_output(&mut self, deadline: Instant) -> io::Result<()> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(());
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut output = libc::pollfd {
+            fd: self.reader.get_ref().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // safe, output is a single pollfd that lives for the whole call
+        let ready = unsafe {
+            libc::poll(
+                &mut output,
+                1,
+                remaining.as_millis().min(i32::MAX as u128) as i32,
+            )
+        };
+        match ready {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the server did not answer in time",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Sends a request and reads messages until its response
+    fn request(&mut self, request: &str, id: u32) -> io::Result<JsonValue> {
+        self.writer.write_all(request.as_bytes())?;
+        self.read_response(id, None)
+    }
+
+    // Reads messages until the response to a request, skipping notifications and answering the
+    // server's own requests. Waits without a limit when no deadline is given
+    fn read_response(&mut self, id: u32, deadline: Option<Instant>) -> io::Result<JsonValue> {
+        loop {
+            if let Some(deadline) = deadline {
+                self.wait_for_output(deadline)?;
+            }
+            let message = lsp_message::read_message(&mut self.reader)?;
+            let response =
+                json::parse(&message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            // requests from the server carry an id too, they get an answer so it doesn't wait
+            if response.has_key("method") && response.has_key("id") {
+                self.writer
+                    .write_all(lsp_message::empty_response(&response["id"]).as_bytes())?;
+                continue;
+            }
+            if response["id"].as_u32() != Some(id) || response.has_key("method") {
+                self.indexing.track(&message);
+                continue;
+            }
+            if response.has_key("error") {
+                return Err(io::Error::other(format!(
+                    "the server answered with an error: {}",
+                    response["error"]["message"]
+                )));
+            }
+            return Ok(response);
+        }
+    }
+
+    // Symbols whose name contains the query, qualified names like Client::send only keep
+    // those in that container
+    pub fn symbols(&mut self, query: &str) -> io::Result<Vec<SymbolMatch>> {
+        let query = QualifiedName::parse(query, self.language, &self.root);
+        self.filters.qualifier = query.qualifier;
+
+        // the server answers with nothing until it has indexed the project
+        let request = lsp_message::symbol_request(&query.name);
+        let response = loop {
+            let response = self.request(&request, lsp_message::SYMBOL_REQUEST_ID)?;
+            if !response["result"].is_empty() {
+                self.indexing.done();
+                break response;
+            }
+            if self.indexing.is_done() {
+                break response;
+            }
+            thread::sleep(lsp_message::RETRY_DELAY);
+        };
+
+        let results = result_handler::collect_results(
+            &response,
+            &query.name,
+            &self.filters,
+            &mut self.writer,
+            &mut self.reader,
+        )?;
+        Ok(results.into_iter().map(SymbolMatch).collect())
+    }
+
+    // What the server says about a position of a file, relative paths are taken from the root
+    pub fn hover(&mut self, path: &Path, line: u32, character: u32) -> io::Result<Option<String>> {
+        let document = paths::path_to_uri(&self.root.join(path).to_string_lossy());
+        let response = self.request(
+            &lsp_message::hover(&document, line, character),
+            lsp_message::HOVER_REQUEST_ID,
+        )?;
+        Ok(get_hover_text(&response["result"]["contents"]))
+    }
+
+    // Where a symbol is used, each named after it with "Reference" as its kind
+    pub fn references(&mut self, symbol: &SymbolMatch) -> io::Result<Vec<SymbolMatch>> {
+        let (line, character) = result_handler::get_name_position(&symbol.0);
+        let response = self.request(
+            &lsp_message::references(symbol.0.location(), line, character),
+            lsp_message::REFERENCES_REQUEST_ID,
+        )?;
+        let results = result_handler::get_location_results(symbol.name(), "Reference", &response);
+        Ok(results.into_iter().map(SymbolMatch).collect())
+    }
+
+    // Asks the server to shut down and exit, killing it if it doesn't exit in time
+    pub fn shutdown(mut self) -> io::Result<()> {
+        self.request(
+            &lsp_message::shutdown_request(),
+            lsp_message::SHUTDOWN_REQUEST_ID,
+        )?;
+        self.writer
+            .write_all(lsp_message::exit_notification().as_bytes())?;
+
+        let asked = Instant::now();
+        while asked.elapsed() < EXIT_TIMEOUT {
+            if self.server.try_wait()?.is_some() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // nothing is left to kill once the server has exited after a shutdown
+        if let Ok(None) = self.server.try_wait() {
+            self.server.kill().ok();
+            self.server.wait().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod client_tests {
+
+    use super::*;
+    use json::{array, object};
+
+    #[test]
+    fn hover_text_is_read_from_every_shape() {
+        let markup = object! { "kind" => "markdown", "value" => "fn parse()" };
+        let marked = array![object! { "language" => "rust", "value" => "usize" }, "docs"];
+
+        assert_eq!(Some("fn parse()".to_string()), get_hover_text(&markup));
+        assert_eq!(Some("usize".to_string()), get_hover_text(&marked));
+        assert_eq!(Some("docs".to_string()), get_hover_text(&"docs".into()));
+        assert_eq!(None, get_hover_text(&JsonValue::Null));
+    }
+}
@@ -0,0 +1,869 @@
+use crate::language::Language;
+use crate::lsp_message;
+use crate::table::{Cell, Column, Elide, Table};
+use colored::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long a server gets to answer shutdown and then to exit before it is killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+// How long restart waits for the new daemon to start listening
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+// How long a new server gets to answer initialize before its client is told to start its own
+const INIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The part of a server only one client uses at a time
+struct Connection {
+    child: Child,
+    stdin: Arc<Mutex<ChildStdin>>,
+    responses: Receiver<String>,
+    init_response: String,
+    // numbers the requests forwarded to the server, see forward
+    next_id: u64,
+}
+
+// A language server the daemon started and initialized for one project root
+struct Server {
+    root: PathBuf,
+    language: Language,
+    pid: u32,
+    started: Instant,
+    indexing: Arc<Mutex<lsp_message::Indexing>>,
+    connection: Mutex<Connection>,
+}
+
+// Empty while the server starts, so only clients of the same project wait for it
+type Slot = Arc<Mutex<Option<Arc<Server>>>>;
+type Servers = Arc<Mutex<HashMap<(PathBuf, Language), Slot>>>;
+
+// Clients being served and when the last one finished, for the idle timeout
+struct Activity {
+    clients: usize,
+    last: Instant,
+}
+
+struct Daemon {
+    servers: Servers,
+    activity: Arc<Mutex<Activity>>,
+    socket: PathBuf,
+    started: Instant,
+}
+
+// What a client asks for in the first line it sends
+#[derive(PartialEq, Debug)]
+enum Request {
+    Query(PathBuf, Language),
+    Status,
+    Stop(Option<PathBuf>),
+}
+
+fn get_uid() -> u32 {
+    // safe, getuid has no preconditions and cannot fail
+    unsafe { libc::getuid() }
+}
+
+// Where the socket goes without XDG_RUNTIME_DIR, created by the daemon for this user only
+fn get_private_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("glsp-{}", get_uid()))
+}
+
+// GLSP_SOCKET overrides where the daemon listens, otherwise it is a per user socket
+pub fn get_socket_path() -> PathBuf {
+    if let Ok(path) = std::env::var("GLSP_SOCKET") {
+        return PathBuf::from(path);
+    }
+
+    let dir = match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => get_private_dir(),
+    };
+    dir.join(format!("glsp-{}.sock", get_uid()))
+}
+
+// Anyone else who could write to the directory could put a socket of their own in its place
+fn check_private_dir(dir: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != get_uid() || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a directory only this user can use",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn connect_socket() -> io::Result<UnixStream> {
+    let socket = get_socket_path();
+    if let Some(dir) = socket.parent().filter(|dir| *dir == get_private_dir()) {
+        check_private_dir(dir)?;
+    }
+    UnixStream::connect(socket)
+}
+
+impl Request {
+    fn to_line(&self) -> String {
+        match self {
+            Request::Query(root, language) => {
+                format!("query\t{}\t{}\n", root.display(), language.language_id())
+            }
+            Request::Status => "status\n".to_string(),
+            Request::Stop(Some(root)) => format!("stop\t{}\n", root.display()),
+            Request::Stop(None) => "stop\n".to_string(),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Request> {
+        let mut parts = line.trim_end_matches('\n').split('\t');
+        let request = match (parts.next()?, parts.next(), parts.next()) {
+            ("query", Some(root), Some(language)) if !root.is_empty() => {
+                Request::Query(PathBuf::from(root), Language::from_name(language)?)
+            }
+            ("status", None, None) => Request::Status,
+            ("stop", Some(root), None) if !root.is_empty() => {
+                Request::Stop(Some(PathBuf::from(root)))
+            }
+            ("stop", None, None) => Request::Stop(None),
+            _ => return None,
+        };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(request),
+        }
+    }
+}
+
+const READY: &str = "ready";
+const INDEXING: &str = "indexing";
+// Another client is using the project's server
+const BUSY: &str = "busy";
+
+// Connects to a running daemon that has a server for the project, and says whether the server
+// has finished indexing. None means the caller should start its own server
+pub fn connect(
+    root: &Path,
+    language: Language,
+) -> Option<(UnixStream, BufReader<UnixStream>, bool)> {
+    let mut stream = connect_socket().ok()?;
+    let request = Request::Query(root.to_path_buf(), language);
+    stream.write_all(request.to_line().as_bytes()).ok()?;
+
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut reply = String::new();
+    reader.read_line(&mut reply).ok()?;
+    let indexed = match reply.trim_end() {
+        READY => true,
+        INDEXING => false,
+        _ => return None,
+    };
+    Some((stream, reader, indexed))
+}
+
+fn is_response_to(message: &json::JsonValue, id: &json::JsonValue) -> bool {
+    message["method"].is_null() && message["id"] == *id
+}
+
+fn write_to_server(stdin: &Mutex<ChildStdin>, message: &str) -> io::Result<()> {
+    stdin.lock().unwrap().write_all(message.as_bytes())
+}
+
+// Runs for as long as the server does, so notifications are seen between queries too.
+// Responses and notifications go to whichever client is waiting, requests from the server
+// get an empty answer
+fn read_server_messages(
+    stdout: ChildStdout,
+    stdin: Arc<Mutex<ChildStdin>>,
+    indexing: Arc<Mutex<lsp_message::Indexing>>,
+    responses: Sender<String>,
+) {
+    let mut stdout = BufReader::new(stdout);
+    while let Ok(content) = lsp_message::read_message(&mut stdout) {
+        let message = match json::parse(&content) {
+            Ok(message) => message,
+            Err(_err) => continue,
+        };
+
+        match message["method"].as_str() {
+            Some(method) if message["id"].is_null() => {
+                indexing
+                    .lock()
+                    .unwrap()
+                    .track_message(method, &message["params"]);
+                // passed on too, so the client waiting for a query sees the progress
+                if responses.send(content).is_err() {
+                    return;
+                }
+            }
+            Some(_method) => {
+                let reply = lsp_message::empty_response(&message["id"]);
+                if write_to_server(&stdin, &reply).is_err() {
+                    return;
+                }
+            }
+            None => {
+                if responses.send(content).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn with_id(mut message: json::JsonValue, id: json::JsonValue) -> String {
+    message["id"] = id;
+    message.dump()
+}
+
+impl Connection {
+    // Notifications and answers the last client left unread are of no use to the next
+    fn discard_pending(&mut self) {
+        while self.responses.try_recv().is_ok() {}
+    }
+
+    // Waits without a limit when no timeout is given, queries can take as long as indexing does.
+    // Notifications read on the way are passed to the client when there is one
+    fn read_response(
+        &mut self,
+        id: &json::JsonValue,
+        timeout: Option<Duration>,
+        mut client: Option<&mut dyn Write>,
+    ) -> io::Result<String> {
+        let exited = || io::Error::new(io::ErrorKind::BrokenPipe, "the server exited");
+        loop {
+            let content = match timeout {
+                Some(timeout) => match self.responses.recv_timeout(timeout) {
+                    Ok(content) => content,
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "the server did not answer",
+                        ))
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return Err(exited()),
+                },
+                None => self.responses.recv().map_err(|_err| exited())?,
+            };
+
+            if let Ok(message) = json::parse(&content) {
+                if is_response_to(&message, id) {
+                    return Ok(content);
+                }
+                if let (Some(client), true) = (client.as_mut(), message["id"].is_null()) {
+                    client.write_all(lsp_message::frame_message(&content).as_bytes())?;
+                }
+            }
+        }
+    }
+
+    // The daemon owns the server's lifecycle, so a client's initialize is answered from
+    // the first one and its initialized, shutdown and exit never reach the server
+    fn forward(&mut self, content: &str, client: &mut dyn Write) -> io::Result<()> {
+        let message = json::parse(content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        match message["method"].as_str() {
+            Some("initialize") => {
+                client.write_all(lsp_message::frame_message(&self.init_response).as_bytes())
+            }
+            Some("initialized") | Some("shutdown") | Some("exit") => Ok(()),
+            _ if message["id"].is_null() => {
+                write_to_server(&self.stdin, &lsp_message::frame_message(content))
+            }
+            _ => {
+                // every client numbers its requests alike, so the server is sent ids of the
+                // daemon's own that a late answer to an earlier client can't match
+                let client_id = message["id"].clone();
+                let server_id = json::JsonValue::from(format!("glsp-daemon-{}", self.next_id));
+                self.next_id += 1;
+                let request = with_id(message, server_id.clone());
+                write_to_server(&self.stdin, &lsp_message::frame_message(&request))?;
+
+                let response = self.read_response(&server_id, None, Some(&mut *client))?;
+                let response = json::parse(&response)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                let response = with_id(response, client_id);
+                client.write_all(lsp_message::frame_message(&response).as_bytes())
+            }
+        }
+    }
+
+    // Asks the server to shut down and exit, killing it if it takes too long
+    fn shutdown(&mut self) {
+        let shutdown_id = json::JsonValue::from(lsp_message::SHUTDOWN_REQUEST_ID);
+        if write_to_server(&self.stdin, &lsp_message::shutdown_request()).is_ok()
+            && self
+                .read_response(&shutdown_id, Some(SHUTDOWN_TIMEOUT), None)
+                .is_ok()
+        {
+            write_to_server(&self.stdin, &lsp_message::exit_notification()).ok();
+        }
+
+        let asked = Instant::now();
+        while asked.elapsed() < EXIT_TIMEOUT {
+            if let Ok(Some(_status)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+impl Server {
+    fn start(root: &Path, language: Language) -> io::Result<Server> {
+        let mut child = crate::run_server(language, root)?;
+        let stdin = Arc::new(Mutex::new(child.stdin.take().unwrap()));
+        let stdout = child.stdout.take().unwrap();
+        let indexing = Arc::new(Mutex::new(lsp_message::Indexing::new(
+            lsp_message::INDEXING_TIMEOUT,
+        )));
+        let (sender, responses) = mpsc::channel();
+        {
+            let stdin = stdin.clone();
+            let indexing = indexing.clone();
+            thread::spawn(move || read_server_messages(stdout, stdin, indexing, sender));
+        }
+
+        let mut connection = Connection {
+            child,
+            stdin,
+            responses,
+            init_response: String::new(),
+            next_id: 0,
+        };
+        write_to_server(
+            &connection.stdin,
+            &lsp_message::init_request(&root.to_string_lossy()),
+        )?;
+        let init_id = json::JsonValue::from(lsp_message::INIT_REQUEST_ID);
+        match connection.read_response(&init_id, Some(INIT_TIMEOUT), None) {
+            Ok(response) => connection.init_response = response,
+            Err(e) => {
+                connection.child.kill().ok();
+                connection.child.wait().ok();
+                return Err(e);
+            }
+        }
+        write_to_server(&connection.stdin, &lsp_message::init_notification())?;
+
+        Ok(Server {
+            root: root.to_path_buf(),
+            language,
+            pid: connection.child.id(),
+            started: Instant::now(),
+            indexing,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    // The same answer a client is given when its query starts
+    fn get_state(&self) -> &'static str {
+        if self.indexing.lock().unwrap().is_done() {
+            READY
+        } else {
+            INDEXING
+        }
+    }
+
+    // For a server whose connection a client holds, which gives no chance to ask politely.
+    // The client's session ends with it, and reaps the process
+    fn kill(&self) {
+        // safe, the pid is our own child's, which is not reaped while its client holds it
+        unsafe {
+            libc::kill(self.pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+}
+
+// Resident memory of a process in kilobytes, from /proc where it is available
+fn get_memory_kb(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn format_memory(kb: Option<u64>) -> String {
+    match kb {
+        Some(kb) if kb >= 1024 * 1024 => format!("{:.1}G", kb as f64 / (1024.0 * 1024.0)),
+        Some(kb) if kb >= 1024 => format!("{:.1}M", kb as f64 / 1024.0),
+        Some(kb) => format!("{}K", kb),
+        None => "-".to_string(),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
+impl Daemon {
+    fn get_server(&self, root: PathBuf, language: Language) -> io::Result<Arc<Server>> {
+        let key = (root.clone(), language);
+        let slot = self.servers.lock().unwrap().entry(key).or_default().clone();
+        let mut slot = slot.lock().unwrap();
+        if let Some(server) = slot.as_ref() {
+            return Ok(server.clone());
+        }
+
+        eprintln!(
+            "glsp daemon: starting {} for {}",
+            language.server_command(),
+            root.display()
+        );
+        // a failed start leaves the slot empty for the next client to try again
+        let server = Arc::new(Server::start(&root, language)?);
+        *slot = Some(server.clone());
+        Ok(server)
+    }
+
+    // Clients are served one at a time per server, since every query uses the same request ids
+    fn query(
+        &self,
+        root: PathBuf,
+        language: Language,
+        reader: &mut dyn BufRead,
+        writer: &mut dyn Write,
+    ) -> io::Result<()> {
+        // the client starts its own server when it is told anything but ready or indexing
+        let server = match self.get_server(root.clone(), language) {
+            Ok(server) => server,
+            Err(e) => {
+                writeln!(writer, "error {}", e)?;
+                return Err(e);
+            }
+        };
+        let mut connection = match server.connection.try_lock() {
+            Ok(connection) => connection,
+            // waiting could take as long as the other client's session does
+            Err(TryLockError::WouldBlock) => {
+                writeln!(writer, "{}", BUSY)?;
+                return Ok(());
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("{}", e),
+        };
+        connection.discard_pending();
+        writeln!(writer, "{}", server.get_state())?;
+        loop {
+            // the client hanging up is how a query ends
+            let content = match lsp_message::read_message(reader) {
+                Ok(content) => content,
+                Err(_err) => return Ok(()),
+            };
+
+            if let Err(e) = connection.forward(&content, writer) {
+                // a server that stopped answering is started again by the next client
+                if connection.child.try_wait().ok().flatten().is_some() {
+                    self.servers.lock().unwrap().remove(&(root, language));
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    fn get_status(&self) -> json::JsonValue {
+        let servers = self.servers.lock().unwrap();
+        let mut listed = json::JsonValue::new_array();
+        // servers still starting hold their slot and are left out
+        let started = servers
+            .values()
+            .filter_map(|slot| slot.try_lock().ok().and_then(|server| server.clone()));
+        for server in started {
+            listed
+                .push(json::object! {
+                    "root" => server.root.to_string_lossy().to_string(),
+                    "language" => server.language.language_id(),
+                    "pid" => server.pid,
+                    "memory" => format_memory(get_memory_kb(server.pid)),
+                    "uptime" => format_duration(server.started.elapsed()),
+                    "state" => server.get_state()
+                })
+                .ok();
+        }
+
+        json::object! {
+            "pid" => std::process::id(),
+            "uptime" => format_duration(self.started.elapsed()),
+            "memory" => format_memory(get_memory_kb(std::process::id())),
+            "servers" => listed
+        }
+    }
+
+    // Shuts down the servers for one root, or all of them, and says how many there were
+    fn stop_servers(&self, root: Option<&Path>) -> usize {
+        let stopped: Vec<Slot> = {
+            let mut servers = self.servers.lock().unwrap();
+            let keys: Vec<(PathBuf, Language)> = servers
+                .keys()
+                .filter(|(server_root, _)| root.is_none_or(|root| root == server_root))
+                .cloned()
+                .collect();
+            keys.iter().filter_map(|key| servers.remove(key)).collect()
+        };
+        // a server that is starting is stopped once it has started
+        let stopped: Vec<Arc<Server>> = stopped
+            .iter()
+            .filter_map(|slot| slot.lock().unwrap().take())
+            .collect();
+
+        for server in &stopped {
+            eprintln!(
+                "glsp daemon: stopping {} for {}",
+                server.language.server_command(),
+                server.root.display()
+            );
+            match server.connection.try_lock() {
+                Ok(mut connection) => connection.shutdown(),
+                // a session such as glsp --watch can hold the connection for as long as it runs
+                Err(TryLockError::WouldBlock) => server.kill(),
+                Err(TryLockError::Poisoned(e)) => panic!("{}", e),
+            }
+        }
+        stopped.len()
+    }
+
+    fn quit(&self) -> ! {
+        self.stop_servers(None);
+        fs::remove_file(&self.socket).ok();
+        std::process::exit(0);
+    }
+
+    fn handle_client(&self, stream: UnixStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        // a second daemon checking whether this one is alive hangs up without a word
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        match Request::parse(&line) {
+            Some(Request::Query(root, language)) => {
+                self.query(root, language, &mut reader, &mut writer)
+            }
+            Some(Request::Status) => writer.write_all(self.get_status().dump().as_bytes()),
+            Some(Request::Stop(Some(root))) => {
+                let stopped = self.stop_servers(Some(&root));
+                writeln!(writer, "{}", stopped)
+            }
+            Some(Request::Stop(None)) => {
+                let count = self
+                    .servers
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .filter(|slot| !matches!(slot.try_lock().as_deref(), Ok(None)))
+                    .count();
+                writeln!(writer, "{}", count)?;
+                writer.flush()?;
+                self.quit();
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed request",
+            )),
+        }
+    }
+
+    // Checks on the daemon every few seconds and quits once no client came for the timeout
+    fn watch_idle(&self, timeout: Duration) {
+        let interval = timeout.min(Duration::from_secs(5));
+        loop {
+            thread::sleep(interval);
+            let idle = {
+                let activity = self.activity.lock().unwrap();
+                activity.clients == 0 && activity.last.elapsed() >= timeout
+            };
+            if idle {
+                eprintln!(
+                    "glsp daemon: idle for {}, shutting down",
+                    format_duration(timeout)
+                );
+                self.quit();
+            }
+        }
+    }
+
+    fn begin_client(&self) {
+        self.activity.lock().unwrap().clients += 1;
+    }
+
+    fn end_client(&self) {
+        let mut activity = self.activity.lock().unwrap();
+        activity.clients -= 1;
+        activity.last = Instant::now();
+    }
+}
+
+fn bind(socket: &Path) -> io::Result<UnixListener> {
+    if let Some(dir) = socket.parent().filter(|dir| *dir == get_private_dir()) {
+        if !dir.exists() {
+            fs::DirBuilder::new().mode(0o700).create(dir)?;
+        }
+        check_private_dir(dir)?;
+    }
+
+    if let Ok(metadata) = fs::symlink_metadata(socket) {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("a daemon is already listening on {}", socket.display()),
+            ));
+        }
+        // only our own socket that nobody answers on was left behind by a daemon that did
+        // not shut down cleanly
+        if !metadata.file_type().is_socket() || metadata.uid() != get_uid() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is in the way of the daemon's socket", socket.display()),
+            ));
+        }
+        fs::remove_file(socket)?;
+    }
+
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+// An idle timeout of None keeps the daemon running until it is stopped
+pub fn run(idle_timeout: Option<Duration>) -> io::Result<()> {
+    let socket = get_socket_path();
+    let listener = bind(&socket)?;
+    eprintln!("glsp daemon: listening on {}", socket.display());
+
+    let daemon = Arc::new(Daemon {
+        servers: Arc::new(Mutex::new(HashMap::new())),
+        activity: Arc::new(Mutex::new(Activity {
+            clients: 0,
+            last: Instant::now(),
+        })),
+        socket,
+        started: Instant::now(),
+    });
+
+    if let Some(timeout) = idle_timeout {
+        let daemon = daemon.clone();
+        thread::spawn(move || daemon.watch_idle(timeout));
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_err) => continue,
+        };
+
+        let daemon = daemon.clone();
+        daemon.begin_client();
+        thread::spawn(move || {
+            if let Err(e) = daemon.handle_client(stream) {
+                eprintln!("glsp daemon: {}", e);
+            }
+            daemon.end_client();
+        });
+    }
+
+    Ok(())
+}
+
+// Sends a control request and returns everything the daemon answers with
+fn send_request(request: &Request) -> io::Result<String> {
+    let mut stream = connect_socket()
+        .map_err(|_err| io::Error::new(io::ErrorKind::NotConnected, "no daemon is running"))?;
+    stream.write_all(request.to_line().as_bytes())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+pub fn print_status(max_width: Option<usize>, heading_color: Color) -> io::Result<()> {
+    let reply = send_request(&Request::Status)?;
+    let status = json::parse(&reply)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    println!(
+        "daemon pid {}, up {}, {} on {}",
+        status["pid"],
+        status["uptime"],
+        status["memory"],
+        get_socket_path().display()
+    );
+    if status["servers"].is_empty() {
+        println!("no servers running");
+        return Ok(());
+    }
+
+    let mut table = Table::new(vec![
+        Column::new("Root", Elide::Middle, 16),
+        Column::new("Language", Elide::Never, 8),
+        Column::new("Pid", Elide::Never, 3),
+        Column::new("Memory", Elide::Never, 6),
+        Column::new("Uptime", Elide::Never, 6),
+        Column::new("State", Elide::Never, 5),
+    ]);
+    for server in status["servers"].members() {
+        table.push_row(
+            ["root", "language", "pid", "memory", "uptime", "state"]
+                .iter()
+                .map(|key| Cell::new(server[*key].to_string()))
+                .collect(),
+        );
+    }
+
+    let widths = table.get_column_widths(max_width);
+    println!("{}", table.get_heading_str(&widths, heading_color));
+    for i in 0..status["servers"].len() {
+        println!("{}", table.get_row_str(i, &widths));
+    }
+    Ok(())
+}
+
+// Stops the servers for a root, or every server and the daemon itself
+pub fn stop(root: Option<&Path>) -> io::Result<usize> {
+    let reply = send_request(&Request::Stop(root.map(|root| root.to_path_buf())))?;
+    reply
+        .trim()
+        .parse()
+        .map_err(|_err| io::Error::new(io::ErrorKind::InvalidData, "unexpected reply"))
+}
+
+fn is_listening() -> bool {
+    connect_socket().is_ok()
+}
+
+// Stops any running daemon and starts a new one in the background
+pub fn restart(idle_timeout: Option<Duration>) -> io::Result<()> {
+    if is_listening() {
+        stop(None)?;
+    }
+    while is_listening() {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command.arg("daemon");
+    if let Some(timeout) = idle_timeout {
+        command
+            .arg("--idle-timeout")
+            .arg(timeout.as_secs().to_string());
+    }
+    // its own process group, so it outlives the terminal it was started from
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+
+    let started = Instant::now();
+    while !is_listening() {
+        if started.elapsed() > START_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the daemon did not start listening",
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod daemon_tests {
+
+    use super::*;
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = vec![
+            Request::Query(PathBuf::from("/home/me/my project"), Language::Rust),
+            Request::Status,
+            Request::Stop(Some(PathBuf::from("/home/me/project"))),
+            Request::Stop(None),
+        ];
+        for request in requests {
+            assert_eq!(Some(&request), Request::parse(&request.to_line()).as_ref());
+        }
+    }
+
+    #[test]
+    fn malformed_requests_are_rejected() {
+        assert_eq!(None, Request::parse("query\t/home/me/project\n"));
+        assert_eq!(None, Request::parse("query\t\trust\n"));
+        assert_eq!(None, Request::parse("query\t/home/me/project\tcobol\n"));
+        assert_eq!(None, Request::parse("status\textra\n"));
+        assert_eq!(None, Request::parse("\n"));
+    }
+
+    #[test]
+    fn server_requests_are_not_responses() {
+        let id = json::JsonValue::from(10);
+        let response = json::parse(r#"{"jsonrpc":"2.0","id":10,"result":[]}"#).unwrap();
+        let request =
+            json::parse(r#"{"jsonrpc":"2.0","id":10,"method":"client/registerCapability"}"#)
+                .unwrap();
+
+        assert!(is_response_to(&response, &id));
+        assert!(!is_response_to(&request, &id));
+    }
+
+    #[test]
+    fn forwarded_requests_keep_the_clients_id() {
+        let request = json::parse(r#"{"jsonrpc":"2.0","id":10,"method":"workspace/symbol"}"#);
+        let sent = with_id(request.unwrap(), "glsp-daemon-3".into());
+        assert!(sent.contains(r#""id":"glsp-daemon-3""#));
+
+        let response = json::parse(r#"{"jsonrpc":"2.0","id":"glsp-daemon-3","result":[]}"#);
+        assert!(with_id(response.unwrap(), 10.into()).contains(r#""id":10"#));
+    }
+
+    #[test]
+    fn sockets_only_go_in_private_dirs() {
+        let dir = std::env::temp_dir().join(format!("glsp-daemon-dir-{}", std::process::id()));
+        fs::DirBuilder::new().mode(0o700).create(&dir).unwrap();
+        assert!(check_private_dir(&dir).is_ok());
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(check_private_dir(&dir).is_err());
+
+        // a file in the way of the socket is left alone
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        let socket = dir.join("glsp.sock");
+        fs::write(&socket, "not a socket").unwrap();
+        assert!(bind(&socket).is_err());
+        assert!(socket.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn durations_and_memory_are_readable() {
+        assert_eq!("42s", format_duration(Duration::from_secs(42)));
+        assert_eq!("3m 5s", format_duration(Duration::from_secs(185)));
+        assert_eq!("2h 1m", format_duration(Duration::from_secs(7260)));
+        assert_eq!("512K", format_memory(Some(512)));
+        assert_eq!("1.5M", format_memory(Some(1536)));
+        assert_eq!("-", format_memory(None));
+    }
+}
@@ -0,0 +1,122 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+// How an editor is told where in a file to start
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum LineSyntax {
+    // vi, gedit and anything unknown: +12 file
+    Plus,
+    // vim and neovim can take the column too: "+call cursor(12, 4)" file
+    VimCursor,
+    // emacs, kakoune and micro: +12:4 file
+    PlusColon,
+    // nano: +12,4 file
+    PlusComma,
+    // VS Code and its forks: --goto file:12:4
+    Goto,
+    // helix, sublime text and zed: file:12:4
+    Suffix,
+    // JetBrains IDEs: --line 12 --column 4 file
+    LineFlag,
+}
+
+fn get_line_syntax(program: &str) -> LineSyntax {
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program);
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+
+    match name {
+        "vim" | "nvim" | "gvim" | "mvim" => LineSyntax::VimCursor,
+        "emacs" | "emacsclient" | "kak" | "micro" => LineSyntax::PlusColon,
+        "nano" => LineSyntax::PlusComma,
+        "code" | "code-insiders" | "codium" | "cursor" => LineSyntax::Goto,
+        "hx" | "helix" | "subl" | "sublime_text" | "zed" => LineSyntax::Suffix,
+        "idea" | "pycharm" | "goland" | "webstorm" | "clion" | "rustrover" => LineSyntax::LineFlag,
+        _ => LineSyntax::Plus,
+    }
+}
+
+// $VISUAL before $EDITOR, like most tools that open an editor
+fn get_editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+// Arguments placing the cursor, line and column counted from 1
+fn get_position_args(program: &str, path: &Path, line: u32, column: u32) -> Vec<String> {
+    let path = path.to_string_lossy().to_string();
+    match get_line_syntax(program) {
+        LineSyntax::Plus => vec![format!("+{}", line), path],
+        LineSyntax::VimCursor => vec![format!("+call cursor({}, {})", line, column), path],
+        LineSyntax::PlusColon => vec![format!("+{}:{}", line, column), path],
+        LineSyntax::PlusComma => vec![format!("+{},{}", line, column), path],
+        LineSyntax::Goto => vec![
+            "--goto".to_string(),
+            format!("{}:{}:{}", path, line, column),
+        ],
+        LineSyntax::Suffix => vec![format!("{}:{}:{}", path, line, column)],
+        LineSyntax::LineFlag => vec![
+            "--line".to_string(),
+            line.to_string(),
+            "--column".to_string(),
+            column.to_string(),
+            path,
+        ],
+    }
+}
+
+// Opens the file at a line and column counted from 1 and waits for the editor to exit
+pub fn open(path: &Path, line: u32, column: u32) -> io::Result<()> {
+    let editor = get_editor();
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    let status = Command::new(program)
+        .args(words)
+        .args(get_position_args(program, path, line, column))
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod editor_tests {
+
+    use super::*;
+
+    fn get_args(program: &str) -> Vec<String> {
+        get_position_args(program, Path::new("src/lib.rs"), 12, 4)
+    }
+
+    #[test]
+    fn terminal_editors_take_plus_line() {
+        assert_eq!(vec!["+12", "src/lib.rs"], get_args("vi"));
+        assert_eq!(
+            vec!["+call cursor(12, 4)", "src/lib.rs"],
+            get_args("/usr/bin/nvim")
+        );
+        assert_eq!(vec!["+12:4", "src/lib.rs"], get_args("emacsclient"));
+        assert_eq!(vec!["+12,4", "src/lib.rs"], get_args("nano"));
+    }
+
+    #[test]
+    fn other_editors_take_their_own_syntax() {
+        assert_eq!(vec!["--goto", "src/lib.rs:12:4"], get_args("code"));
+        assert_eq!(vec!["src/lib.rs:12:4"], get_args("hx"));
+        assert_eq!(
+            vec!["--line", "12", "--column", "4", "src/lib.rs"],
+            get_args("idea")
+        );
+    }
+}
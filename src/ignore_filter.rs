@@ -0,0 +1,150 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Highest precedence first, like ripgrep a .ignore overrides a .gitignore
+const IGNORE_FILES: [&str; 3] = [".glspignore", ".ignore", ".gitignore"];
+
+pub struct IgnoreFilter {
+    root: PathBuf,
+    // ignore files found in each directory, loaded the first time a result lands under it
+    loaded: RefCell<HashMap<PathBuf, Vec<Gitignore>>>,
+}
+
+fn load_ignore_file(dir: &Path, name: &str) -> Option<Gitignore> {
+    let path = dir.join(name);
+    if !path.is_file() {
+        return None;
+    }
+
+    let mut builder = GitignoreBuilder::new(dir);
+    builder.add(&path);
+    builder.build().ok()
+}
+
+fn load_dir(dir: &Path, is_root: bool) -> Vec<Gitignore> {
+    let mut ignores: Vec<Gitignore> = IGNORE_FILES
+        .iter()
+        .filter_map(|name| load_ignore_file(dir, name))
+        .collect();
+
+    if is_root {
+        let exclude = dir.join(".git").join("info").join("exclude");
+        if exclude.is_file() {
+            let mut builder = GitignoreBuilder::new(dir);
+            builder.add(&exclude);
+            if let Ok(gitignore) = builder.build() {
+                ignores.push(gitignore);
+            }
+        }
+    }
+
+    ignores
+}
+
+impl IgnoreFilter {
+    pub fn new(root: &Path) -> IgnoreFilter {
+        IgnoreFilter {
+            root: root.to_path_buf(),
+            loaded: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn get_match(&self, dir: &Path, path: &Path) -> Option<bool> {
+        let mut loaded = self.loaded.borrow_mut();
+        let ignores = loaded
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| load_dir(dir, dir == self.root));
+
+        for gitignore in ignores.iter() {
+            match gitignore.matched_path_or_any_parents(path, false) {
+                Match::Ignore(_) => return Some(true),
+                Match::Whitelist(_) => return Some(false),
+                Match::None => continue,
+            }
+        }
+
+        None
+    }
+
+    // Only paths inside the root are checked, the deepest ignore file with an opinion wins
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if !path.starts_with(&self.root) {
+            return false;
+        }
+
+        let dirs = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root));
+
+        for dir in dirs {
+            if let Some(ignored) = self.get_match(dir, path) {
+                return ignored;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod ignore_filter_tests {
+
+    use super::*;
+    use std::fs;
+
+    fn get_test_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("glsp-ignore-{}-{}", name, std::process::id()));
+        fs::create_dir_all(root.join("src").join("gen")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        root
+    }
+
+    #[test]
+    fn gitignore_hides_matching_paths() {
+        let root = get_test_root("gitignore");
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        let filter = IgnoreFilter::new(&root);
+
+        assert!(filter.is_ignored(&root.join("target").join("debug").join("build.rs")));
+        assert!(!filter.is_ignored(&root.join("src").join("lib.rs")));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn glspignore_overrides_gitignore() {
+        let root = get_test_root("precedence");
+        fs::write(root.join(".gitignore"), "*.rs\n").unwrap();
+        fs::write(root.join(".glspignore"), "!src/lib.rs\n").unwrap();
+        let filter = IgnoreFilter::new(&root);
+
+        assert!(!filter.is_ignored(&root.join("src").join("lib.rs")));
+        assert!(filter.is_ignored(&root.join("src").join("main.rs")));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn nested_ignore_file_applies_beneath_it() {
+        let root = get_test_root("nested");
+        fs::write(root.join("src").join(".ignore"), "gen/\n").unwrap();
+        let filter = IgnoreFilter::new(&root);
+
+        assert!(filter.is_ignored(&root.join("src").join("gen").join("parser.rs")));
+        assert!(!filter.is_ignored(&root.join("gen").join("parser.rs")));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn paths_outside_root_are_not_ignored() {
+        let root = get_test_root("outside");
+        fs::write(root.join(".gitignore"), "*\n").unwrap();
+        let filter = IgnoreFilter::new(&root);
+
+        assert!(!filter.is_ignored(Path::new("/usr/lib/rust/lib.rs")));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+use crate::language::Language;
+use crate::lsp_message::FileChange;
+use crate::paths;
+use ignore::WalkBuilder;
+use json::{object, JsonValue};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Bumped whenever the layout of the index file changes, older files are then rebuilt
+const INDEX_VERSION: u32 = 2;
+
+struct IndexedFile {
+    hash: String,
+    stamp: FileStamp,
+    symbols: Vec<JsonValue>,
+}
+
+// The symbols of every source file in a project, each file stored with the hash of the
+// contents they were read from so a changed file is noticed without asking the server
+pub struct Index {
+    path: PathBuf,
+    files: BTreeMap<String, IndexedFile>,
+    // set when anything changed since the index was loaded
+    dirty: bool,
+}
+
+// FNV-1a, chosen over std's hasher because its output must not change between builds
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+pub fn hash_contents(bytes: &[u8]) -> String {
+    format!("{:016x}", hash_bytes(bytes))
+}
+
+pub fn get_cache_dir() -> Option<PathBuf> {
+    match std::env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("glsp")),
+        _ => std::env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".cache").join("glsp")),
+    }
+}
+
+// One file per project and language, named after a hash of the root
+pub fn get_index_path(root: &Path, language: Language) -> Option<PathBuf> {
+    let name = format!(
+        "{}-{}.json",
+        hash_contents(root.to_string_lossy().as_bytes()),
+        language.language_id()
+    );
+    Some(get_cache_dir()?.join("index").join(name))
+}
+
+// Source files of the language under the root, skipping what the ignore files exclude
+pub fn get_source_files(root: &Path, language: Language) -> Vec<String> {
+    let extensions = language.extensions();
+    let mut files: Vec<String> = WalkBuilder::new(root)
+        .require_git(false)
+        .add_custom_ignore_filename(".glspignore")
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| extensions.contains(&extension))
+        })
+        .filter_map(|entry| {
+            let relative = entry.path().strip_prefix(root).ok()?;
+            Some(relative.to_string_lossy().replace('\\', "/"))
+        })
+        .collect();
+
+    files.sort();
+    files
+}
+
+// What the file system says about a file, compared before its contents are read again
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FileStamp {
+    modified: u64,
+    size: u64,
+}
+
+pub fn get_file_stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(FileStamp {
+        modified: modified.as_nanos() as u64,
+        size: metadata.len(),
+    })
+}
+
+// Files that differ from what the index holds, by their path from the root
+#[derive(Default, PartialEq, Debug)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Changes {
+    // The files whose symbols have to be read again
+    pub fn get_stale_files(&self) -> Vec<String> {
+        self.added.iter().chain(&self.modified).cloned().collect()
+    }
+
+    pub fn get_events(&self, root: &Path) -> Vec<(String, FileChange)> {
+        let events = [
+            (&self.added, FileChange::Created),
+            (&self.modified, FileChange::Changed),
+            (&self.removed, FileChange::Deleted),
+        ];
+        events
+            .iter()
+            .flat_map(|(files, change)| {
+                files.iter().map(move |file| {
+                    let path = root.join(file);
+                    (paths::path_to_uri(&path.to_string_lossy()), *change)
+                })
+            })
+            .collect()
+    }
+}
+
+impl Index {
+    pub fn new(path: PathBuf) -> Index {
+        Index {
+            path,
+            files: BTreeMap::new(),
+            dirty: true,
+        }
+    }
+
+    pub fn load(path: &Path) -> Option<Index> {
+        let contents = fs::read_to_string(path).ok()?;
+        let parsed = json::parse(&contents).ok()?;
+        if parsed["version"].as_u32() != Some(INDEX_VERSION) {
+            return None;
+        }
+
+        let mut index = Index::new(path.to_path_buf());
+        for (file, entry) in parsed["files"].entries() {
+            index.files.insert(
+                file.to_string(),
+                IndexedFile {
+                    hash: entry["hash"].as_str()?.to_string(),
+                    stamp: FileStamp {
+                        modified: entry["modified"].as_u64()?,
+                        size: entry["size"].as_u64()?,
+                    },
+                    symbols: entry["symbols"].members().cloned().collect(),
+                },
+            );
+        }
+        index.dirty = false;
+        Some(index)
+    }
+
+    // Written next to the old file and renamed over it so a reader never sees half an index
+    pub fn save(&self) -> io::Result<()> {
+        let mut files = JsonValue::new_object();
+        for (file, entry) in &self.files {
+            files[file.as_str()] = object! {
+                "hash" => entry.hash.as_str(),
+                "modified" => entry.stamp.modified,
+                "size" => entry.stamp.size,
+                "symbols" => JsonValue::Array(entry.symbols.clone())
+            };
+        }
+        let contents = object! {
+            "version" => INDEX_VERSION,
+            "files" => files
+        };
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let partial = self.path.with_extension("json.tmp");
+        fs::write(&partial, contents.dump())?;
+        fs::rename(&partial, &self.path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn insert(
+        &mut self,
+        file: String,
+        hash: String,
+        stamp: FileStamp,
+        symbols: Vec<JsonValue>,
+    ) {
+        self.files.insert(
+            file,
+            IndexedFile {
+                hash,
+                stamp,
+                symbols,
+            },
+        );
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn symbol_count(&self) -> usize {
+        self.files.values().map(|entry| entry.symbols.len()).sum()
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    // Compares the index with the source files under the root. Only files whose stamp moved are
+    // hashed, those that still hash the same just take the new stamp, and files that are gone
+    // are dropped along with their symbols
+    pub fn get_changes(&mut self, root: &Path, language: Language) -> Changes {
+        let mut changes = Changes::default();
+        let files = get_source_files(root, language);
+
+        for file in &files {
+            let stamp = match get_file_stamp(&root.join(file)) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            let entry = match self.files.get_mut(file) {
+                Some(entry) => entry,
+                None => {
+                    changes.added.push(file.clone());
+                    continue;
+                }
+            };
+            if entry.stamp == stamp {
+                continue;
+            }
+
+            let hash = fs::read(root.join(file)).map(|contents| hash_contents(&contents));
+            if hash.ok().as_ref() == Some(&entry.hash) {
+                entry.stamp = stamp;
+                self.dirty = true;
+            } else {
+                changes.modified.push(file.clone());
+            }
+        }
+
+        changes.removed = self
+            .files
+            .keys()
+            .filter(|file| files.binary_search(file).is_err())
+            .cloned()
+            .collect();
+        for file in &changes.removed {
+            self.files.remove(file);
+            self.dirty = true;
+        }
+
+        changes
+    }
+
+    // Every stored symbol in a response shaped like a workspace/symbol result
+    pub fn get_response(&self) -> JsonValue {
+        let symbols: Vec<JsonValue> = self
+            .files
+            .values()
+            .flat_map(|entry| entry.symbols.iter().cloned())
+            .collect();
+        object! { "result" => JsonValue::Array(symbols) }
+    }
+}
+
+#[cfg(test)]
+mod index_tests {
+
+    use super::*;
+    use json::array;
+
+    fn get_test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("glsp-index-{}-{}", name, std::process::id()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        root
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!("cbf29ce484222325", hash_contents(b""));
+        assert_eq!("af63dc4c8601ec8c", hash_contents(b"a"));
+    }
+
+    #[test]
+    fn source_files_are_filtered_by_extension_and_ignore_files() {
+        let root = get_test_root("walk");
+        fs::write(root.join("src").join("lib.rs"), "fn lib() {}").unwrap();
+        fs::write(root.join("src").join("gen.rs"), "fn gen() {}").unwrap();
+        fs::write(root.join("README.md"), "# readme").unwrap();
+        fs::write(root.join(".glspignore"), "src/gen.rs\n").unwrap();
+
+        assert_eq!(
+            vec!["src/lib.rs".to_string()],
+            get_source_files(&root, Language::Rust)
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn index_round_trips_through_file() {
+        let root = get_test_root("save");
+        let path = root.join("index").join("p-rust.json");
+        let symbol = object! { "name" => "parse", "kind" => 12, "detail" => "fn parse()" };
+
+        let mut index = Index::new(path.clone());
+        let stamp = FileStamp {
+            modified: 1_600_000_000_123_456_789,
+            size: 12,
+        };
+        index.insert(
+            "src/lib.rs".to_string(),
+            "01".to_string(),
+            stamp,
+            vec![symbol],
+        );
+        index.save().unwrap();
+
+        let loaded = Index::load(&path).unwrap();
+        assert_eq!(1, loaded.symbol_count());
+        assert_eq!(stamp, loaded.files["src/lib.rs"].stamp);
+        assert!(!loaded.is_dirty());
+        assert_eq!(
+            array![object! { "name" => "parse", "kind" => 12, "detail" => "fn parse()" }],
+            loaded.get_response()["result"]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn only_changed_files_are_stale() {
+        let root = get_test_root("changes");
+        let lib = root.join("src").join("lib.rs");
+        let old = root.join("src").join("old.rs");
+        fs::write(&lib, "fn lib() {}").unwrap();
+        fs::write(&old, "fn old() {}").unwrap();
+
+        let mut index = Index::new(root.join("index.json"));
+        for file in get_source_files(&root, Language::Rust) {
+            let contents = fs::read(root.join(&file)).unwrap();
+            let stamp = get_file_stamp(&root.join(&file)).unwrap();
+            index.insert(file, hash_contents(&contents), stamp, Vec::new());
+        }
+        assert_eq!(Changes::default(), index.get_changes(&root, Language::Rust));
+
+        fs::write(&lib, "fn lib() { changed() }").unwrap();
+        fs::remove_file(&old).unwrap();
+        fs::write(root.join("src").join("new.rs"), "fn new() {}").unwrap();
+        let changes = index.get_changes(&root, Language::Rust);
+
+        assert_eq!(vec!["src/new.rs".to_string()], changes.added);
+        assert_eq!(vec!["src/lib.rs".to_string()], changes.modified);
+        assert_eq!(vec!["src/old.rs".to_string()], changes.removed);
+        assert_eq!(
+            vec!["src/new.rs".to_string(), "src/lib.rs".to_string()],
+            changes.get_stale_files()
+        );
+        assert_eq!(1, index.file_count());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn touched_file_with_same_contents_is_not_stale() {
+        let root = get_test_root("touched");
+        let lib = root.join("src").join("lib.rs");
+        fs::write(&lib, "fn lib() {}").unwrap();
+
+        let mut index = Index::new(root.join("index.json"));
+        let stamp = FileStamp {
+            modified: 0,
+            size: 11,
+        };
+        index.insert(
+            "src/lib.rs".to_string(),
+            hash_contents(b"fn lib() {}"),
+            stamp,
+            Vec::new(),
+        );
+        index.dirty = false;
+
+        assert_eq!(Changes::default(), index.get_changes(&root, Language::Rust));
+        assert!(index.is_dirty());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+use std::io;
+use std::path::{Component, Path};
+use std::process::{Child, Command, Stdio};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Language {
+    Rust,
+}
+
+impl Language {
+    pub fn from_name(name: &str) -> Option<Language> {
+        match name {
+            "rust" => Some(Language::Rust),
+            _ => None,
+        }
+    }
+
+    pub fn server_command(&self) -> &'static str {
+        match self {
+            Language::Rust => "rls",
+        }
+    }
+
+    pub fn server_args(&self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &[],
+        }
+    }
+
+    // Extensions of the source files indexed for the language
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &["rs"],
+        }
+    }
+
+    // The languageId sent with documents opened on the server
+    pub fn language_id(&self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+        }
+    }
+
+    // Dependency and toolchain sources left out of results unless --include-deps is given
+    pub fn default_excludes(&self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &[".cargo/", ".rustup/", "vendor/"],
+        }
+    }
+
+    // Joins the segments of a qualified name, e.g. net::http::Client
+    pub fn separator(&self) -> &'static str {
+        match self {
+            Language::Rust => "::",
+        }
+    }
+
+    // The module a file defines, which follows from the file's path under src
+    pub fn module_path(&self, relative_path: &Path) -> Vec<String> {
+        let (source_dir, module_files): (&str, &[&str]) = match self {
+            Language::Rust => ("src", &["mod", "lib", "main"]),
+        };
+
+        let mut segments: Vec<String> = Vec::new();
+        let without_extension = relative_path.with_extension("");
+        for component in without_extension.components() {
+            if let Component::Normal(segment) = component {
+                let segment = segment.to_string_lossy().to_string();
+                // everything up to the source directory is not part of the module path
+                if segment == source_dir {
+                    segments.clear();
+                } else {
+                    segments.push(segment);
+                }
+            }
+        }
+
+        if let Some(last) = segments.last() {
+            if module_files.contains(&last.as_str()) {
+                segments.pop();
+            }
+        }
+
+        segments
+    }
+}
+
+pub fn run_server(language: Language, root: &Path) -> io::Result<Child> {
+    // stderr is never read, a server kept running by the daemon would fill up the pipe
+    let instance = Command::new(language.server_command())
+        .args(language.server_args())
+        .current_dir(root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(instance)
+}
+
+#[cfg(test)]
+mod language_tests {
+
+    use super::*;
+
+    #[test]
+    fn rust_module_path_follows_src_layout() {
+        let rust = Language::Rust;
+        assert_eq!(
+            vec!["net", "http"],
+            rust.module_path(Path::new("src/net/http.rs"))
+        );
+        assert_eq!(vec!["net"], rust.module_path(Path::new("src/net/mod.rs")));
+        assert!(rust.module_path(Path::new("src/lib.rs")).is_empty());
+        assert_eq!(
+            vec!["parser"],
+            rust.module_path(Path::new("crates/core/src/parser.rs"))
+        );
+    }
+
+    #[test]
+    fn separator_depends_on_language() {
+        assert_eq!("::", Language::Rust.separator());
+    }
+}
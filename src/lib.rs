@@ -0,0 +1,31 @@
+// glsp as a library, for tools that want symbols from a language server without parsing the
+// table the command line prints. Client, SymbolMatch and Language are the whole API
+mod client;
+// shared with the glsp binary, which uses the parts the client leaves unused
+#[allow(dead_code)]
+mod changed;
+#[allow(dead_code)]
+mod ignore_filter;
+#[allow(dead_code)]
+mod language;
+#[allow(dead_code)]
+mod lsp_message;
+#[allow(dead_code)]
+mod path_filter;
+#[allow(dead_code)]
+mod paths;
+#[allow(dead_code)]
+mod position;
+#[allow(dead_code)]
+mod qualified_name;
+#[allow(dead_code)]
+mod result_handler;
+#[allow(dead_code)]
+mod source_context;
+#[allow(dead_code)]
+mod table;
+#[allow(dead_code)]
+mod theme;
+
+pub use client::{Client, SymbolMatch};
+pub use language::Language;
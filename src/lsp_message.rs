@@ -1,18 +1,30 @@
+use crate::paths;
 use json::{array, object, JsonValue};
+use std::collections::HashSet;
 use std::io;
 use std::io::BufRead;
+use std::time::{Duration, Instant};
 
 pub const INIT_REQUEST_ID: u32 = 0;
 pub const INIT_NOTIFY_ID: u32 = 1;
 pub const SYMBOL_REQUEST_ID: u32 = 10;
 pub const HOVER_REQUEST_ID: u32 = 20;
+pub const DOCUMENT_SYMBOL_REQUEST_ID: u32 = 30;
+pub const SHUTDOWN_REQUEST_ID: u32 = 40;
+pub const REFERENCES_REQUEST_ID: u32 = 50;
+pub const DEFINITION_REQUEST_ID: u32 = 60;
+
+// How long a query that found nothing is repeated while the server may still be indexing
+pub const INDEXING_TIMEOUT: Duration = Duration::from_secs(30);
+// The pause between those repeats
+pub const RETRY_DELAY: Duration = Duration::from_millis(100);
 
 struct InitRequest {
     json_message: JsonValue,
 }
 
 impl InitRequest {
-    fn new() -> InitRequest {
+    fn new(root: &str) -> InitRequest {
         InitRequest {
             json_message: object! {
                 "id" => INIT_REQUEST_ID,
@@ -20,8 +32,8 @@ impl InitRequest {
                 "method" => "initialize",
                 "params" => object!{
                     "processid" => get_pid(),
-                    "rootPath" => get_cur_working_dir(),
-                    "rootUri" => get_project_uri(),
+                    "rootPath" => root,
+                    "rootUri" => paths::path_to_uri(root),
                     "capabilities" => object!{
                         "workspace" => object!{
               "applyEdit" => true,
@@ -65,6 +77,9 @@ impl InitRequest {
                     25,
                     26
                   ]
+                },
+                "tagSupport" => object!{
+                  "valueSet" => array![1]
                 }
               },
               "executeCommand" => object!{
@@ -181,6 +196,9 @@ impl InitRequest {
                     25,
                     26
                   ]
+                },
+                "tagSupport" => object!{
+                  "valueSet" => array![1]
                 }
               },
               "codeAction" => object!{
@@ -248,10 +266,10 @@ struct SymbolRequest {
 }
 
 impl SymbolRequest {
-    fn new(symbol_name: &str) -> SymbolRequest {
+    fn new(symbol_name: &str, id: u32) -> SymbolRequest {
         SymbolRequest {
             json_message: object! {
-                "id" => SYMBOL_REQUEST_ID,
+                "id" => id,
                 "jsonrpc" => 2.0,
                 "method" => "workspace/symbol",
                 "params" => object!{
@@ -262,6 +280,24 @@ impl SymbolRequest {
     }
 }
 
+struct CancelRequest {
+    json_message: JsonValue,
+}
+
+impl CancelRequest {
+    fn new(id: u32) -> CancelRequest {
+        CancelRequest {
+            json_message: object! {
+                "jsonrpc" => 2.0,
+                "method" => "$/cancelRequest",
+                "params" => object!{
+                  "id" => id
+                }
+            },
+        }
+    }
+}
+
 struct Hover {
     json_message: JsonValue,
 }
@@ -287,27 +323,219 @@ impl Hover {
     }
 }
 
+struct References {
+    json_message: JsonValue,
+}
+
+impl References {
+    fn new(document: &str, line: u32, character: u32, id: u32) -> References {
+        References {
+            json_message: object! {
+                "id" => id,
+                "jsonrpc" => 2.0,
+                "method" => "textDocument/references",
+                "params" => object!{
+                  "textDocument" => object!{
+                    "uri" => document
+                    },
+                  "position" => object!{
+                    "line" => line,
+                    "character" => character
+                  },
+                  "context" => object!{
+                    "includeDeclaration" => true
+                  }
+                }
+            },
+        }
+    }
+}
+
+struct Definition {
+    json_message: JsonValue,
+}
+
+impl Definition {
+    fn new(document: &str, line: u32, character: u32) -> Definition {
+        Definition {
+            json_message: object! {
+                "id" => DEFINITION_REQUEST_ID,
+                "jsonrpc" => 2.0,
+                "method" => "textDocument/definition",
+                "params" => object!{
+                  "textDocument" => object!{
+                    "uri" => document
+                    },
+                  "position" => object!{
+                    "line" => line,
+                    "character" => character
+                  }
+                }
+            },
+        }
+    }
+}
+
+struct DocumentSymbolRequest {
+    json_message: JsonValue,
+}
+
+impl DocumentSymbolRequest {
+    fn new(document: &str) -> DocumentSymbolRequest {
+        DocumentSymbolRequest {
+            json_message: object! {
+                "id" => DOCUMENT_SYMBOL_REQUEST_ID,
+                "jsonrpc" => 2.0,
+                "method" => "textDocument/documentSymbol",
+                "params" => object!{
+                  "textDocument" => object!{
+                    "uri" => document
+                    }
+                }
+            },
+        }
+    }
+}
+
+struct DidOpen {
+    json_message: JsonValue,
+}
+
+impl DidOpen {
+    fn new(document: &str, language_id: &str, text: &str) -> DidOpen {
+        DidOpen {
+            json_message: object! {
+                "jsonrpc" => 2.0,
+                "method" => "textDocument/didOpen",
+                "params" => object!{
+                  "textDocument" => object!{
+                    "uri" => document,
+                    "languageId" => language_id,
+                    "version" => 1,
+                    "text" => text
+                    }
+                }
+            },
+        }
+    }
+}
+
+struct DidClose {
+    json_message: JsonValue,
+}
+
+impl DidClose {
+    fn new(document: &str) -> DidClose {
+        DidClose {
+            json_message: object! {
+                "jsonrpc" => 2.0,
+                "method" => "textDocument/didClose",
+                "params" => object!{
+                  "textDocument" => object!{
+                    "uri" => document
+                    }
+                }
+            },
+        }
+    }
+}
+
+struct EmptyResponse {
+    json_message: JsonValue,
+}
+
+impl EmptyResponse {
+    fn new(id: &JsonValue) -> EmptyResponse {
+        EmptyResponse {
+            json_message: object! {
+                "id" => id.clone(),
+                "jsonrpc" => 2.0,
+                "result" => JsonValue::Null
+            },
+        }
+    }
+}
+
+// FileChangeType of a workspace/didChangeWatchedFiles event
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FileChange {
+    Created = 1,
+    Changed = 2,
+    Deleted = 3,
+}
+
+struct DidChangeWatchedFiles {
+    json_message: JsonValue,
+}
+
+impl DidChangeWatchedFiles {
+    fn new(changes: &[(String, FileChange)]) -> DidChangeWatchedFiles {
+        let mut events = JsonValue::new_array();
+        for (document, change) in changes {
+            events
+                .push(object! {
+                    "uri" => document.as_str(),
+                    "type" => *change as u32
+                })
+                .ok();
+        }
+
+        DidChangeWatchedFiles {
+            json_message: object! {
+                "jsonrpc" => 2.0,
+                "method" => "workspace/didChangeWatchedFiles",
+                "params" => object!{
+                  "changes" => events
+                }
+            },
+        }
+    }
+}
+
+struct Shutdown {
+    json_message: JsonValue,
+}
+
+impl Shutdown {
+    fn new() -> Shutdown {
+        Shutdown {
+            json_message: object! {
+                "id" => SHUTDOWN_REQUEST_ID,
+                "jsonrpc" => 2.0,
+                "method" => "shutdown"
+            },
+        }
+    }
+}
+
+struct Exit {
+    json_message: JsonValue,
+}
+
+impl Exit {
+    fn new() -> Exit {
+        Exit {
+            json_message: object! {
+                "jsonrpc" => 2.0,
+                "method" => "exit"
+            },
+        }
+    }
+}
+
 fn get_pid() -> u32 {
     // get parent pid for transaction
     std::process::id()
 }
 
-fn get_cur_working_dir() -> String {
+pub fn get_cur_working_dir() -> String {
     // get absolute path to current dir
     let cwd_path = std::env::current_dir().expect("Error when getting cwd");
-    let p = cwd_path
+
+    cwd_path
         .into_os_string()
         .into_string()
-        .expect("Error when converting cwd to string");
-
-    p
-}
-
-fn get_project_uri() -> String {
-    let p = get_cur_working_dir();
-    let uri = "file://".to_string() + &p;
-
-    uri
+        .expect("Error when converting cwd to string")
 }
 
 fn get_msg_size(message: &json::JsonValue) -> usize {
@@ -315,7 +543,7 @@ fn get_msg_size(message: &json::JsonValue) -> usize {
 }
 
 fn get_formatted_message_str(payload: &json::JsonValue) -> String {
-    let size = get_msg_size(&payload);
+    let size = get_msg_size(payload);
 
     // create the header
     let header = format!("Content-Length: {}\r\n\r\n", size);
@@ -324,8 +552,13 @@ fn get_formatted_message_str(payload: &json::JsonValue) -> String {
     (header + &payload.dump()).to_string()
 }
 
-pub fn init_request() -> String {
-    get_formatted_message_str(&InitRequest::new().json_message)
+// Frames a message that was read with read_message so it can be passed on unchanged
+pub fn frame_message(content: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+}
+
+pub fn init_request(root: &str) -> String {
+    get_formatted_message_str(&InitRequest::new(root).json_message)
 }
 
 pub fn init_notification() -> String {
@@ -333,19 +566,73 @@ pub fn init_notification() -> String {
 }
 
 pub fn symbol_request(symbol_name: &str) -> String {
-    get_formatted_message_str(&SymbolRequest::new(symbol_name).json_message)
+    get_formatted_message_str(&SymbolRequest::new(symbol_name, SYMBOL_REQUEST_ID).json_message)
+}
+
+// For callers with several queries in flight that tell the answers apart by id
+pub fn numbered_symbol_request(symbol_name: &str, id: u32) -> String {
+    get_formatted_message_str(&SymbolRequest::new(symbol_name, id).json_message)
+}
+
+// Asks the server to drop a request whose answer is no longer wanted
+pub fn cancel_request(id: u32) -> String {
+    get_formatted_message_str(&CancelRequest::new(id).json_message)
 }
 
 pub fn hover(document: &str, line: u32, character: u32) -> String {
     get_formatted_message_str(&Hover::new(document, line, character).json_message)
 }
 
-fn get_message_content_size<R: BufRead>(input: &mut R) -> Result<usize, io::Error> {
+pub fn references(document: &str, line: u32, character: u32) -> String {
+    numbered_references(document, line, character, REFERENCES_REQUEST_ID)
+}
+
+pub fn numbered_references(document: &str, line: u32, character: u32, id: u32) -> String {
+    get_formatted_message_str(&References::new(document, line, character, id).json_message)
+}
+
+pub fn definition(document: &str, line: u32, character: u32) -> String {
+    get_formatted_message_str(&Definition::new(document, line, character).json_message)
+}
+
+pub fn document_symbol_request(document: &str) -> String {
+    get_formatted_message_str(&DocumentSymbolRequest::new(document).json_message)
+}
+
+// The server reads an open document from the text sent here rather than from disk
+pub fn did_open(document: &str, language_id: &str, text: &str) -> String {
+    get_formatted_message_str(&DidOpen::new(document, language_id, text).json_message)
+}
+
+pub fn did_close(document: &str) -> String {
+    get_formatted_message_str(&DidClose::new(document).json_message)
+}
+
+// Tells a server that has been running a while which files changed on disk since it read them
+pub fn did_change_watched_files(changes: &[(String, FileChange)]) -> String {
+    get_formatted_message_str(&DidChangeWatchedFiles::new(changes).json_message)
+}
+
+// Answers a request from the server, such as client/registerCapability, with nothing. The
+// server may wait on requests that go unanswered
+pub fn empty_response(id: &JsonValue) -> String {
+    get_formatted_message_str(&EmptyResponse::new(id).json_message)
+}
+
+pub fn shutdown_request() -> String {
+    get_formatted_message_str(&Shutdown::new().json_message)
+}
+
+pub fn exit_notification() -> String {
+    get_formatted_message_str(&Exit::new().json_message)
+}
+
+fn get_message_content_size<R: BufRead + ?Sized>(input: &mut R) -> Result<usize, io::Error> {
     // Read in the "Content-Length: xx" part.
     let mut content_size: Option<usize> = None;
     loop {
         let mut buffer = String::new();
-        input.read_line(&mut buffer).expect("Error parsing message"); 
+        input.read_line(&mut buffer)?;
 
         // End of input.
         if buffer.is_empty() {
@@ -384,7 +671,7 @@ fn get_message_content_size<R: BufRead>(input: &mut R) -> Result<usize, io::Erro
         // Get the size from the content length field
         match header_name.as_ref() {
             "content-length:" => {
-                content_size = Some(usize::from_str_radix(header_value, 10).map_err(|_e| {
+                content_size = Some(header_value.parse::<usize>().map_err(|_e| {
                     io::Error::new(io::ErrorKind::InvalidData, "Couldn't read size")
                 })?);
             }
@@ -399,8 +686,8 @@ fn get_message_content_size<R: BufRead>(input: &mut R) -> Result<usize, io::Erro
     }
 }
 
-pub fn read_message<R: BufRead>(input: &mut R) -> Result<String, io::Error> {
-    let content_size = get_message_content_size(input).unwrap();
+pub fn read_message<R: BufRead + ?Sized>(input: &mut R) -> Result<String, io::Error> {
+    let content_size = get_message_content_size(input)?;
 
     let mut content = vec![0; content_size]; // Initialize the message array size
     input.read_exact(&mut content)?; // Read the exact number of bytes from the input stream into the array
@@ -408,6 +695,82 @@ pub fn read_message<R: BufRead>(input: &mut R) -> Result<String, io::Error> {
     String::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
+// Servers report indexing with $/progress, rls with its older window/progress
+fn track_progress(method: &str, params: &JsonValue, progress: &mut HashSet<String>) {
+    match method {
+        "$/progress" => match params["value"]["kind"].as_str() {
+            Some("begin") => {
+                progress.insert(params["token"].dump());
+            }
+            Some("end") => {
+                progress.remove(&params["token"].dump());
+            }
+            _ => {}
+        },
+        "window/progress" => {
+            if params["done"].as_bool().unwrap_or(false) {
+                progress.remove(&params["id"].dump());
+            } else {
+                progress.insert(params["id"].dump());
+            }
+        }
+        _ => {}
+    }
+}
+
+// Whether a server may still be indexing. It is done once every progress it began has
+// ended, or a query found something, or the time budget ran out for servers that never say
+pub struct Indexing {
+    started: Instant,
+    budget: Duration,
+    // progress tokens the server has begun and not yet ended
+    progress: HashSet<String>,
+    ended: bool,
+}
+
+impl Indexing {
+    pub fn new(budget: Duration) -> Indexing {
+        Indexing {
+            started: Instant::now(),
+            budget,
+            progress: HashSet::new(),
+            ended: false,
+        }
+    }
+
+    // Looks for progress in a message read from the server, anything else is ignored
+    pub fn track(&mut self, content: &str) {
+        let message = match json::parse(content) {
+            Ok(message) => message,
+            Err(_err) => return,
+        };
+        if let (Some(method), true) = (message["method"].as_str(), message["id"].is_null()) {
+            self.track_message(method, &message["params"]);
+        }
+    }
+
+    pub fn track_message(&mut self, method: &str, params: &JsonValue) {
+        let indexing = !self.progress.is_empty();
+        track_progress(method, params, &mut self.progress);
+        if indexing && self.progress.is_empty() {
+            self.ended = true;
+        }
+    }
+
+    pub fn done(&mut self) {
+        self.ended = true;
+    }
+
+    // Progress the server reports after it was done, e.g. for changed files, counts again
+    pub fn is_indexing(&self) -> bool {
+        !self.progress.is_empty()
+    }
+
+    pub fn is_done(&self) -> bool {
+        (self.ended && self.progress.is_empty()) || self.started.elapsed() >= self.budget
+    }
+}
+
 #[cfg(test)]
 mod lsp_message_tests {
 
@@ -420,7 +783,7 @@ mod lsp_message_tests {
 
     #[test]
     fn init_req_has_proper_id() {
-        let init_req_json = init_request();
+        let init_req_json = init_request("/p");
         assert!(init_req_json.contains("\"id\":0"));
     }
 
@@ -480,4 +843,115 @@ mod lsp_message_tests {
         let hover_json = hover("fooBar.rs", 420, 69);
         assert!(hover_json.contains("\"character\":69"));
     }
+
+    #[test]
+    fn numbered_symbol_req_is_cancelled_by_id() {
+        assert!(numbered_symbol_request("parse", 105).contains("\"id\":105"));
+        let cancel = cancel_request(105);
+        assert!(cancel.contains("\"method\":\"$/cancelRequest\""));
+        assert!(cancel.contains("\"params\":{\"id\":105}"));
+    }
+
+    #[test]
+    fn server_requests_are_answered_by_their_id() {
+        let reply = empty_response(&"register-7".into());
+        assert!(reply.contains("\"id\":\"register-7\""));
+        assert!(reply.contains("\"result\":null"));
+        assert!(!reply.contains("\"method\""));
+    }
+
+    #[test]
+    fn references_req_includes_declaration() {
+        let request = references("file:///p/src/lib.rs", 12, 7);
+        assert!(request.contains("\"id\":50"));
+        assert!(request.contains("\"method\":\"textDocument/references\""));
+        assert!(request.contains("\"includeDeclaration\":true"));
+    }
+
+    #[test]
+    fn definition_req_has_passed_position() {
+        let request = definition("file:///p/src/lib.rs", 12, 7);
+        assert!(request.contains("\"id\":60"));
+        assert!(request.contains("\"line\":12"));
+        assert!(request.contains("\"character\":7"));
+    }
+
+    #[test]
+    fn document_symbol_req_id_is_30() {
+        assert_eq!(30, DOCUMENT_SYMBOL_REQUEST_ID);
+    }
+
+    #[test]
+    fn document_symbol_req_has_passed_document() {
+        let request = document_symbol_request("file:///p/src/lib.rs");
+        assert!(request.contains("\"id\":30"));
+        assert!(request.contains("\"uri\":\"file:///p/src/lib.rs\""));
+    }
+
+    #[test]
+    fn did_open_sends_document_text() {
+        let notification = did_open("file:///p/src/lib.rs", "rust", "fn main() {}");
+        assert!(notification.contains("\"method\":\"textDocument/didOpen\""));
+        assert!(notification.contains("\"languageId\":\"rust\""));
+        assert!(notification.contains("\"text\":\"fn main() {}\""));
+        assert!(!notification.contains("\"id\""));
+    }
+
+    #[test]
+    fn watched_files_carry_change_types() {
+        let notification = did_change_watched_files(&[
+            ("file:///p/src/lib.rs".to_string(), FileChange::Changed),
+            ("file:///p/src/old.rs".to_string(), FileChange::Deleted),
+        ]);
+        assert!(notification.contains("\"method\":\"workspace/didChangeWatchedFiles\""));
+        assert!(notification.contains("{\"uri\":\"file:///p/src/lib.rs\",\"type\":2}"));
+        assert!(notification.contains("{\"uri\":\"file:///p/src/old.rs\",\"type\":3}"));
+    }
+
+    #[test]
+    fn shutdown_req_has_proper_id() {
+        assert!(shutdown_request().contains("\"id\":40"));
+        assert!(!exit_notification().contains("\"id\""));
+    }
+
+    #[test]
+    fn progress_tracks_begin_and_end() {
+        let mut progress = HashSet::new();
+        let begin = json::parse(r#"{"token":"index","value":{"kind":"begin"}}"#).unwrap();
+        let end = json::parse(r#"{"token":"index","value":{"kind":"end"}}"#).unwrap();
+
+        track_progress("$/progress", &begin, &mut progress);
+        assert_eq!(1, progress.len());
+        track_progress("$/progress", &end, &mut progress);
+        assert!(progress.is_empty());
+
+        let rls = json::parse(r#"{"id":"progress_0","title":"Indexing"}"#).unwrap();
+        let rls_done = json::parse(r#"{"id":"progress_0","done":true}"#).unwrap();
+        track_progress("window/progress", &rls, &mut progress);
+        assert_eq!(1, progress.len());
+        track_progress("window/progress", &rls_done, &mut progress);
+        assert!(progress.is_empty());
+    }
+
+    #[test]
+    fn indexing_is_done_once_progress_ends() {
+        let mut indexing = Indexing::new(Duration::from_secs(60));
+        assert!(!indexing.is_done());
+
+        indexing.track(r#"{"jsonrpc":"2.0","method":"window/progress","params":{"id":"progress_0","title":"Indexing"}}"#);
+        assert!(indexing.is_indexing());
+        indexing.track(r#"{"jsonrpc":"2.0","id":10,"result":[]}"#);
+        assert!(!indexing.is_done());
+        indexing.track(r#"{"jsonrpc":"2.0","method":"window/progress","params":{"id":"progress_0","done":true}}"#);
+        assert!(indexing.is_done());
+    }
+
+    #[test]
+    fn indexing_is_done_once_the_budget_runs_out() {
+        assert!(Indexing::new(Duration::from_secs(0)).is_done());
+
+        let mut indexing = Indexing::new(Duration::from_secs(60));
+        indexing.done();
+        assert!(indexing.is_done());
+    }
 }
@@ -1,26 +1,83 @@
 extern crate colored;
+extern crate globset;
+extern crate ignore;
 extern crate json;
+extern crate libc;
 #[macro_use]
 extern crate clap;
 
 use clap::App;
+use language::run_server;
+mod batch;
+mod changed;
+mod daemon;
+mod editor;
+mod ignore_filter;
+mod index;
+mod language;
 mod lsp_message;
+mod path_filter;
+mod paths;
+mod picker;
+mod position;
+mod qualified_name;
+mod repl;
 mod result_handler;
+mod source_context;
+mod table;
+mod terminal;
+mod theme;
+mod watcher;
 use std::io;
-use std::io::{BufReader, Write};
-use std::process::{Command, Stdio};
-
-fn run_server() -> Result<std::process::Child, io::Error> {
-    let instance = match Command::new("rls")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(instance) => instance,
-        Err(error) => return Err(error),
-    };
-    Ok(instance)
+use std::io::{BufRead, BufReader, IsTerminal, Write};
+use std::time::Instant;
+
+// Reader and writer for the server's messages, through the daemon when one is running, and
+// whether the server may still be indexing
+type ServerConnection = (
+    Box<dyn Write>,
+    Box<dyn BufRead + Send>,
+    Option<std::process::Child>,
+    lsp_message::Indexing,
+);
+
+fn connect_server(language: language::Language, root: &std::path::Path) -> ServerConnection {
+    if let Some((writer, reader, indexed)) = daemon::connect(root, language) {
+        let mut indexing = lsp_message::Indexing::new(lsp_message::INDEXING_TIMEOUT);
+        if indexed {
+            indexing.done();
+        }
+        return (Box::new(writer), Box::new(reader), None, indexing);
+    }
+    start_server(language, root)
+}
+
+// A server of our own even when the daemon is running, for sessions that cancel requests
+fn start_server(language: language::Language, root: &std::path::Path) -> ServerConnection {
+    let mut server_instance = run_server(language, root).unwrap_or_else(|e| {
+        eprintln!(
+            "error: Unable to start {}: {}",
+            language.server_command(),
+            e
+        );
+        std::process::exit(1);
+    });
+    let writer = server_instance.stdin.take().unwrap();
+    let reader = BufReader::new(server_instance.stdout.take().unwrap());
+    (
+        Box::new(writer),
+        Box::new(reader),
+        Some(server_instance),
+        lsp_message::Indexing::new(lsp_message::INDEXING_TIMEOUT),
+    )
+}
+
+// don't leave a server we started running once we have what we need
+fn stop_server(server_instance: Option<std::process::Child>) {
+    if let Some(mut server_instance) = server_instance {
+        server_instance.kill().ok();
+        server_instance.wait().ok();
+    }
 }
 
 fn get_flags(matches: &clap::ArgMatches) -> Vec<String> {
@@ -69,64 +126,719 @@ fn get_flags(matches: &clap::ArgMatches) -> Vec<String> {
         flags.push("Variable".to_string())
     }
 
-    return flags;
+    flags
+}
+
+fn get_kind_filter(matches: &clap::ArgMatches) -> result_handler::KindFilter {
+    let mut include = get_flags(matches);
+    include.extend(get_values(matches, "kind"));
+
+    result_handler::KindFilter::parse(&include, &get_values(matches, "exclude-kind"))
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        })
 }
 
 fn get_symbol_req_response(
-    reader: &mut BufReader<std::process::ChildStdout>,
+    reader: &mut dyn BufRead,
+    indexing: &mut lsp_message::Indexing,
 ) -> json::JsonValue {
     let mut res: String;
     let check_str = format!("\"id\":{}", lsp_message::SYMBOL_REQUEST_ID);
     loop {
-        let y = match lsp_message::read_message(reader) {
-            Ok(message) => Some(message),
-            Err(_err) => None,
-        };
+        let y = lsp_message::read_message(reader).ok();
         res = y.unwrap();
         if res.contains(&check_str) {
             break;
         }
+        indexing.track(&res);
+    }
+
+    json::parse(&res).unwrap()
+}
+
+fn get_values(matches: &clap::ArgMatches, name: &str) -> Vec<String> {
+    match matches.values_of(name) {
+        Some(values) => values.map(|value| value.to_string()).collect(),
+        None => Vec::new(),
+    }
+}
+
+fn get_path_filter(
+    matches: &clap::ArgMatches,
+    language: language::Language,
+) -> path_filter::PathFilter {
+    let includes = get_values(matches, "glob");
+    let mut excludes = get_values(matches, "exclude");
+    if !matches.is_present("include-deps") {
+        excludes.extend(language.default_excludes().iter().map(|p| p.to_string()));
+    }
+
+    let root = std::path::PathBuf::from(lsp_message::get_cur_working_dir());
+    path_filter::PathFilter::new(&root, &includes, &excludes).unwrap_or_else(|e| {
+        eprintln!("error: Invalid glob pattern: {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn get_deprecated_filter(matches: &clap::ArgMatches) -> result_handler::DeprecatedFilter {
+    if matches.is_present("deprecated-only") {
+        result_handler::DeprecatedFilter::Only
+    } else if matches.is_present("no-deprecated") {
+        result_handler::DeprecatedFilter::Hide
+    } else {
+        result_handler::DeprecatedFilter::Any
+    }
+}
+
+fn get_line_range(matches: &clap::ArgMatches) -> Option<position::LineRange> {
+    let value = matches.value_of("lines")?;
+    match position::LineRange::parse(value) {
+        Some(lines) => Some(lines),
+        None => {
+            eprintln!(
+                "error: Invalid --lines '{}', expected START:END counted from 1",
+                value
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+fn get_position(matches: &clap::ArgMatches) -> Option<position::Position> {
+    let value = matches.value_of("at")?;
+    match position::Position::parse(value) {
+        Some(position) => Some(position),
+        None => {
+            eprintln!(
+                "error: Invalid --at '{}', expected FILE:LINE:COL counted from 1",
+                value
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// The index answers without a server, positions, diffs and watching still need one. It only
+// holds the project's own files, so dependencies need the server too
+fn can_use_index(matches: &clap::ArgMatches) -> bool {
+    !matches.is_present("no-cache")
+        && !matches.is_present("watch")
+        && !matches.is_present("include-deps")
+        && !matches.is_present("at")
+        && matches.subcommand_matches("changed").is_none()
+}
+
+// the queries of a batch, from stdin or a file, None when searching for one symbol
+fn get_batch_queries(matches: &clap::ArgMatches) -> Option<Vec<batch::BatchQuery>> {
+    let queries = if matches.is_present("stdin") {
+        batch::read_queries(&mut io::stdin().lock())
+    } else {
+        let path = matches.value_of("queries")?;
+        match std::fs::File::open(path) {
+            Ok(file) => batch::read_queries(&mut BufReader::new(file)),
+            Err(e) => Err(format!("Unable to read {}: {}", path, e)),
+        }
+    };
+
+    match queries {
+        Ok(queries) => Some(queries),
+        Err(e) => {
+            eprintln!("error: Invalid batch query, {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// The query, which clap hands to --open as its value when --open comes first, e.g.
+// "glsp --open parse"
+fn get_query<'a>(matches: &'a clap::ArgMatches) -> Option<&'a str> {
+    matches.value_of("regex").or_else(|| {
+        matches
+            .value_of("open")
+            .filter(|value| value.parse::<usize>().is_err())
+    })
+}
+
+// --open without a number opens the first result
+fn get_open_number(matches: &clap::ArgMatches) -> Option<usize> {
+    if !matches.is_present("open") {
+        return None;
+    }
+    let value = match matches.value_of("open") {
+        // the value was the query
+        Some(value) if matches.value_of("regex").is_none() && get_query(matches) == Some(value) => {
+            "1"
+        }
+        Some(value) => value,
+        None => "1",
+    };
+    match value.parse::<usize>() {
+        Ok(number) if number > 0 => Some(number),
+        _ => {
+            eprintln!(
+                "error: Invalid --open '{}', expected a result number from 1",
+                value
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// The Nth result in the order it is printed
+fn get_open_target(
+    results: &[result_handler::LspResult],
+    number: usize,
+    options: &result_handler::OutputOptions,
+) -> Result<result_handler::LspResult, String> {
+    if results.is_empty() {
+        return Err("No results to open".to_string());
+    }
+    let mut ordered = results.to_vec();
+    result_handler::order_results(&mut ordered, options);
+    let result = ordered.into_iter().nth(number - 1).ok_or_else(|| {
+        format!(
+            "Unable to open result {}, there are only {}",
+            number,
+            results.len()
+        )
+    })?;
+    if result.is_removed() {
+        return Err(format!(
+            "Unable to open result {}, it was removed and is not in the current file",
+            number
+        ));
+    }
+    Ok(result)
+}
+
+fn open_target(target: Option<Result<result_handler::LspResult, String>>) {
+    let result = match target {
+        Some(Ok(result)) => result,
+        Some(Err(e)) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        None => return,
+    };
+    let range = result.range();
+    let path = paths::uri_to_path(result.location());
+    if let Err(e) = editor::open(&path, range.start_line + 1, range.start_character + 1) {
+        eprintln!("error: Unable to open {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+fn get_file_filter(matches: &clap::ArgMatches) -> path_filter::FileFilter {
+    let files = get_values(matches, "file");
+
+    let root = std::path::PathBuf::from(lsp_message::get_cur_working_dir());
+    path_filter::FileFilter::new(&root, &files).unwrap_or_else(|e| {
+        eprintln!("error: Invalid --file glob: {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn get_ignore_filter(matches: &clap::ArgMatches) -> Option<ignore_filter::IgnoreFilter> {
+    if matches.is_present("no-ignore") {
+        return None;
+    }
+
+    let root = std::path::PathBuf::from(lsp_message::get_cur_working_dir());
+    Some(ignore_filter::IgnoreFilter::new(&root))
+}
+
+fn get_context_flags(matches: &clap::ArgMatches) -> source_context::ContextLines {
+    // -C sets both sides, -A and -B override it individually like grep
+    let both = if matches.is_present("context") {
+        value_t!(matches, "context", u32).unwrap_or_else(|e| e.exit())
+    } else {
+        0
+    };
+    let before = if matches.is_present("before") {
+        value_t!(matches, "before", u32).unwrap_or_else(|e| e.exit())
+    } else {
+        both
+    };
+    let after = if matches.is_present("after") {
+        value_t!(matches, "after", u32).unwrap_or_else(|e| e.exit())
+    } else {
+        both
+    };
+
+    source_context::ContextLines::new(before, after)
+}
+
+fn get_path_style(matches: &clap::ArgMatches) -> paths::PathStyle {
+    if matches.is_present("absolute") {
+        return paths::PathStyle::Absolute;
     }
 
-    return json::parse(&res).unwrap();
+    matches
+        .value_of("path-style")
+        .and_then(paths::PathStyle::from_name)
+        .unwrap_or(paths::PathStyle::Relative)
 }
 
-fn get_filename_flag(matches: &clap::ArgMatches) -> String {
-    if matches.is_present("file") {
-        return matches
-            .value_of("file")
-            .expect("There was an error fetching the filename flag")
-            .to_string();
+fn get_output_mode(matches: &clap::ArgMatches) -> result_handler::OutputMode {
+    if matches.is_present("count") {
+        result_handler::OutputMode::Count
+    } else if matches.is_present("stats") {
+        result_handler::OutputMode::Stats
+    } else {
+        result_handler::OutputMode::Results
+    }
+}
+
+fn get_group_flag(matches: &clap::ArgMatches) -> bool {
+    if matches.is_present("group") {
+        return true;
+    }
+    if matches.is_present("no-group") || matches.is_present("sort") {
+        return false;
     }
 
-    return "".to_string();
+    // group by default when a person is reading the output
+    std::io::stdout().is_terminal()
 }
 
-fn notify_initialized(rls_stdin: &mut std::process::ChildStdin) {
+fn notify_initialized(rls_stdin: &mut dyn Write) {
     let full_notify_msg = lsp_message::init_notification();
     rls_stdin
         .write_all(full_notify_msg.as_bytes())
         .expect("Unable to write to RLS child process");
 }
 
+// The server answers with nothing until it has indexed the project, so an empty answer is
+// asked again until indexing is done
 fn get_symbol_response_or_timeout(
     regex: &str,
-    rls_stdin: &mut std::process::ChildStdin,
-    lock: &mut BufReader<std::process::ChildStdout>,
-) -> json::JsonValue {
+    indexing: &mut lsp_message::Indexing,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> (json::JsonValue, u32) {
     let full_req = lsp_message::symbol_request(regex);
-    let mut res_json: json::JsonValue = json::JsonValue::Null;
-    for _ in 0..40000 {
+    let mut requests = 0;
+    loop {
         rls_stdin
             .write_all(full_req.as_bytes())
             .expect("Unable to write to RLS child process");
-        res_json = get_symbol_req_response(lock);
+        requests += 1;
+        let res_json = get_symbol_req_response(lock, indexing);
         if !res_json.to_string().contains("\"result\":[]}") {
-            break;
+            indexing.done();
+            return (res_json, requests);
+        }
+        if indexing.is_done() {
+            return (res_json, requests);
+        }
+        std::thread::sleep(lsp_message::RETRY_DELAY);
+    }
+}
+
+fn get_document_symbol_response(
+    document: &str,
+    indexing: &mut lsp_message::Indexing,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<(json::JsonValue, u32)> {
+    let full_req = lsp_message::document_symbol_request(document);
+    let check_str = format!("\"id\":{}", lsp_message::DOCUMENT_SYMBOL_REQUEST_ID);
+    let mut requests = 0;
+    // like workspace/symbol, the server answers with nothing until it has indexed the file
+    loop {
+        rls_stdin.write_all(full_req.as_bytes())?;
+        requests += 1;
+        let res = loop {
+            let message = lsp_message::read_message(lock)?;
+            if message.contains(&check_str) {
+                break message;
+            }
+            indexing.track(&message);
+        };
+        let res_json =
+            json::parse(&res).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if !res_json["result"].is_empty() {
+            indexing.done();
+            return Ok((res_json, requests));
+        }
+        if indexing.is_done() {
+            return Ok((res_json, requests));
+        }
+        std::thread::sleep(lsp_message::RETRY_DELAY);
+    }
+}
+
+// Opened documents are answered from the text sent, so one request is enough
+fn get_text_symbols(
+    document: &str,
+    text: &str,
+    language: language::Language,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<Vec<json::JsonValue>> {
+    rls_stdin
+        .write_all(lsp_message::did_open(document, language.language_id(), text).as_bytes())?;
+    let mut indexed = lsp_message::Indexing::new(std::time::Duration::from_secs(0));
+    let (response, _requests) =
+        get_document_symbol_response(document, &mut indexed, rls_stdin, lock)?;
+    rls_stdin.write_all(lsp_message::did_close(document).as_bytes())?;
+
+    Ok(position::get_flat_symbols(&response, document))
+}
+
+fn get_side_symbols(
+    repo_root: &std::path::Path,
+    side: &changed::Side,
+    path: &Option<String>,
+    language: language::Language,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<Vec<json::JsonValue>> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(Vec::new()),
+    };
+
+    match changed::read_side(repo_root, side, path) {
+        Some(text) => {
+            let document = paths::path_to_uri(&repo_root.join(path).to_string_lossy());
+            get_text_symbols(&document, &text, language, rls_stdin, lock)
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+// Symbols whose ranges intersect the lines a diff touched, in the order git lists the files
+fn get_changed_symbols(
+    range: Option<&str>,
+    language: language::Language,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<Vec<(json::JsonValue, changed::Change)>> {
+    let cwd = std::path::PathBuf::from(lsp_message::get_cur_working_dir());
+    let repo_root = changed::get_repo_root(&cwd)?;
+    let (old_side, new_side) = changed::get_sides(&repo_root, range)?;
+    let mut diffs = changed::parse_diff(&changed::get_diff(&repo_root, range)?);
+    if new_side == changed::Side::WorkTree {
+        diffs.extend(changed::get_untracked(&repo_root, language.extensions())?);
+    }
+
+    let mut changes = Vec::new();
+    for diff in diffs {
+        let old_symbols = get_side_symbols(
+            &repo_root,
+            &old_side,
+            &diff.old_path,
+            language,
+            rls_stdin,
+            lock,
+        )?;
+        let new_symbols = get_side_symbols(
+            &repo_root,
+            &new_side,
+            &diff.new_path,
+            language,
+            rls_stdin,
+            lock,
+        )?;
+        changes.extend(changed::classify(&old_symbols, &new_symbols, &diff));
+    }
+
+    Ok(changes)
+}
+
+const WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(100);
+// How long after a change the query is asked again, the first answer can come before the
+// server has re-indexed the changed files
+const WATCH_REQUERY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+// The symbols matching the query, or the one enclosing the position when --at was given
+fn get_query_response(
+    regex: &str,
+    position: Option<&position::Position>,
+    root: &std::path::Path,
+    indexing: &mut lsp_message::Indexing,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<(json::JsonValue, u32)> {
+    match position {
+        Some(position) => {
+            let document = paths::path_to_uri(&root.join(&position.path).to_string_lossy());
+            let (response, requests) =
+                get_document_symbol_response(&document, indexing, rls_stdin, lock)?;
+            Ok((
+                position::find_enclosing_symbol(&response, &document, position),
+                requests,
+            ))
+        }
+        None => Ok(get_symbol_response_or_timeout(
+            regex, indexing, rls_stdin, lock,
+        )),
+    }
+}
+
+// Keeps one server running and asks it again whenever a source file changes, redrawing
+// only when the results differ from those on screen
+fn watch_query(
+    language: language::Language,
+    root: &std::path::Path,
+    regex: &str,
+    position: Option<&position::Position>,
+    filters: &result_handler::ResultFilters,
+    options: &result_handler::OutputOptions,
+) {
+    let mut watcher = watcher::Watcher::new(root, language.extensions()).unwrap_or_else(|e| {
+        eprintln!("error: Unable to watch {}: {}", root.display(), e);
+        std::process::exit(1);
+    });
+
+    let (mut writer, mut reader, server_instance, mut indexing) = connect_server(language, root);
+    let rls_stdin = writer.as_mut();
+    let lock = reader.as_mut();
+    rls_stdin
+        .write_all(lsp_message::init_request(&root.to_string_lossy()).as_bytes())
+        .expect("Error writing json dump to stdin");
+    notify_initialized(rls_stdin);
+
+    // only the first query waits for the server to index, later ones take what it has
+    let mut shown: Option<Vec<result_handler::LspResult>> = None;
+    let mut changed = false;
+    loop {
+        let server_start = Instant::now();
+        let (res_json, requests) =
+            match get_query_response(regex, position, root, &mut indexing, rls_stdin, lock) {
+                Ok(response) => response,
+                Err(e) => {
+                    eprintln!("error: Unable to query the server: {}", e);
+                    break;
+                }
+            };
+        let server_time = server_start.elapsed();
+
+        let resolve_start = Instant::now();
+        let results =
+            match result_handler::collect_results(&res_json, regex, filters, rls_stdin, lock) {
+                Ok(results) => results,
+                Err(e) => {
+                    eprintln!("error: Unable to query the server: {}", e);
+                    break;
+                }
+            };
+        if shown.as_ref() != Some(&results) {
+            let timing = result_handler::QueryTiming {
+                server: server_time,
+                requests,
+                resolve: resolve_start.elapsed(),
+            };
+            if io::stdout().is_terminal() {
+                print!("\x1b[2J\x1b[H");
+            }
+            result_handler::print_results(results.clone(), options, &timing);
+            io::stdout().flush().ok();
+            shown = Some(results);
+        }
+
+        // ask again after a change, and for as long as the server reports indexing it
+        let requery = changed || indexing.is_indexing();
+        let idle = if requery {
+            Some(WATCH_REQUERY_DELAY)
+        } else {
+            None
+        };
+        let changes = match watcher.wait(WATCH_DEBOUNCE, idle) {
+            Ok(changes) => changes,
+            Err(e) => {
+                eprintln!("error: Unable to watch {}: {}", root.display(), e);
+                break;
+            }
+        };
+        changed = !changes.is_empty();
+        if !changed {
+            continue;
+        }
+        let events: Vec<(String, lsp_message::FileChange)> = changes
+            .into_iter()
+            .map(|(path, change)| (paths::path_to_uri(&path.to_string_lossy()), change))
+            .collect();
+        rls_stdin
+            .write_all(lsp_message::did_change_watched_files(&events).as_bytes())
+            .expect("Unable to write to RLS child process");
+    }
+
+    stop_server(server_instance);
+}
+
+// Symbols of one file as stored in the index, variables keep the type the server hovers
+fn get_indexed_symbols(
+    document: &str,
+    text: &str,
+    language: language::Language,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<Vec<json::JsonValue>> {
+    let mut symbols = get_text_symbols(document, text, language, rls_stdin, lock)?;
+    for symbol in symbols.iter_mut().filter(|symbol| symbol["kind"] == 13) {
+        let range = position::get_range(&symbol["location"]["range"]);
+        symbol["detail"] = result_handler::get_hover_type(
+            document,
+            range.start_line,
+            range.start_character,
+            rls_stdin,
+            lock,
+        )?
+        .into();
+    }
+    Ok(symbols)
+}
+
+// Reads the given files through the server and stores their symbols in the index, after
+// telling it about the files that changed
+fn index_files(
+    index: &mut index::Index,
+    files: Vec<String>,
+    changes: &[(String, lsp_message::FileChange)],
+    language: language::Language,
+    root: &std::path::Path,
+) -> io::Result<()> {
+    let (mut writer, mut reader, server_instance, _indexing) = connect_server(language, root);
+    let rls_stdin = writer.as_mut();
+    let lock = reader.as_mut();
+    rls_stdin.write_all(lsp_message::init_request(&root.to_string_lossy()).as_bytes())?;
+    notify_initialized(rls_stdin);
+    if !changes.is_empty() {
+        rls_stdin.write_all(lsp_message::did_change_watched_files(changes).as_bytes())?;
+    }
+
+    for file in files {
+        let path = root.join(&file);
+        let stamp = index::get_file_stamp(&path);
+        let contents = std::fs::read(&path);
+        let (stamp, contents) = match (stamp, contents) {
+            (Some(stamp), Ok(contents)) => (stamp, contents),
+            _ => continue,
+        };
+        let document = paths::path_to_uri(&path.to_string_lossy());
+        let text = String::from_utf8_lossy(&contents);
+        let symbols = get_indexed_symbols(&document, &text, language, rls_stdin, lock)?;
+        index.insert(file, index::hash_contents(&contents), stamp, symbols);
+    }
+
+    stop_server(server_instance);
+    Ok(())
+}
+
+fn rebuild_index(language: language::Language, root: &std::path::Path) -> io::Result<index::Index> {
+    let path = index::get_index_path(root, language).ok_or_else(|| {
+        io::Error::other("no cache directory, neither XDG_CACHE_HOME nor HOME is set")
+    })?;
+
+    let mut index = index::Index::new(path);
+    index_files(
+        &mut index,
+        index::get_source_files(root, language),
+        &[],
+        language,
+        root,
+    )?;
+    index.save()?;
+    Ok(index)
+}
+
+fn run_index_command(matches: &clap::ArgMatches, language: language::Language) {
+    let root = std::path::PathBuf::from(lsp_message::get_cur_working_dir());
+    if let ("rebuild", Some(_rebuild_matches)) = matches.subcommand() {
+        match rebuild_index(language, &root) {
+            Ok(index) => println!(
+                "indexed {} symbol(s) in {} file(s) to {}",
+                index.symbol_count(),
+                index.file_count(),
+                index.path().display()
+            ),
+            Err(e) => {
+                eprintln!("error: Unable to rebuild the index: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+// The stored index with the files changed since it was written read again, None when the
+// project has no index yet
+fn load_index(language: language::Language, root: &std::path::Path) -> Option<index::Index> {
+    let mut index = index::Index::load(&index::get_index_path(root, language)?)?;
+    let changes = index.get_changes(root, language);
+
+    let stale = changes.get_stale_files();
+    if !stale.is_empty() {
+        // a server kept warm by the daemon read these files before they changed
+        let events = changes.get_events(root);
+        if let Err(e) = index_files(&mut index, stale, &events, language, root) {
+            eprintln!("error: Unable to refresh the symbol index: {}", e);
+            return None;
         }
     }
 
-    res_json
+    if index.is_dirty() {
+        if let Err(e) = index.save() {
+            eprintln!("warning: Unable to save the symbol index: {}", e);
+        }
+    }
+    Some(index)
+}
+
+// Seconds without a client before the daemon shuts its servers down, 0 to never time out
+fn get_idle_timeout(matches: &clap::ArgMatches) -> Option<std::time::Duration> {
+    let secs = match matches.value_of("idle-timeout") {
+        Some(value) => value.parse::<u64>().unwrap_or_else(|_e| {
+            eprintln!(
+                "error: Invalid --idle-timeout '{}', expected seconds",
+                value
+            );
+            std::process::exit(1);
+        }),
+        None => 30 * 60,
+    };
+
+    if secs == 0 {
+        None
+    } else {
+        Some(std::time::Duration::from_secs(secs))
+    }
+}
+
+fn run_daemon_command(matches: &clap::ArgMatches) {
+    let result = match matches.subcommand() {
+        ("status", Some(_status_matches)) => daemon::print_status(
+            table::get_terminal_width(),
+            theme::Theme::from_env().heading,
+        ),
+        ("stop", Some(stop_matches)) => {
+            let root = stop_matches.value_of("root").map(|root| {
+                std::fs::canonicalize(root).unwrap_or_else(|_e| std::path::PathBuf::from(root))
+            });
+            daemon::stop(root.as_deref()).map(|stopped| match root {
+                Some(root) => println!("stopped {} server(s) for {}", stopped, root.display()),
+                None => println!("stopped the daemon and {} server(s)", stopped),
+            })
+        }
+        ("restart", Some(restart_matches)) => {
+            let idle_timeout = get_idle_timeout(restart_matches);
+            daemon::restart(idle_timeout).map(|_started| {
+                println!(
+                    "daemon listening on {}",
+                    daemon::get_socket_path().display()
+                )
+            })
+        }
+        _ => daemon::run(get_idle_timeout(matches)),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
 }
 
 fn main() {
@@ -134,40 +846,224 @@ fn main() {
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
 
-    // get the passed symbol we're looking for
-    let regex = matches.value_of("regex").unwrap();
+    if let Some(daemon_matches) = matches.subcommand_matches("daemon") {
+        run_daemon_command(daemon_matches);
+        return;
+    }
+
+    // get the passed symbol we're looking for, only its last segment is sent to the server
+    let language = language::Language::Rust;
+    if let Some(index_matches) = matches.subcommand_matches("index") {
+        run_index_command(index_matches, language);
+        return;
+    }
+    let root = std::path::PathBuf::from(lsp_message::get_cur_working_dir());
+    let searched = ["at", "stdin", "queries"]
+        .iter()
+        .any(|name| matches.is_present(name));
+    if get_query(&matches).is_none() && !searched && matches.subcommand_name().is_none() {
+        eprintln!("error: --open needs a query, e.g. glsp parse --open 2");
+        std::process::exit(1);
+    }
+    let query =
+        qualified_name::QualifiedName::parse(get_query(&matches).unwrap_or(""), language, &root);
+    let position = get_position(&matches);
+    let open_number = get_open_number(&matches);
+    let regex = query.name.as_str();
+
+    // which results to keep
+    let filters = result_handler::ResultFilters {
+        files: get_file_filter(&matches),
+        kinds: get_kind_filter(&matches),
+        containers: get_values(&matches, "in"),
+        paths: get_path_filter(&matches, language),
+        ignore: get_ignore_filter(&matches),
+        qualifier: query.qualifier,
+        deprecated: get_deprecated_filter(&matches),
+        lines: get_line_range(&matches),
+    };
+
+    // decide whether to colour before anything is printed
+    let color_choice = matches
+        .value_of("color")
+        .and_then(theme::ColorChoice::from_name)
+        .unwrap_or(theme::ColorChoice::Auto);
+    theme::apply_color_choice(color_choice);
+
+    // how the results should be laid out
+    let options = result_handler::OutputOptions {
+        mode: get_output_mode(&matches),
+        context: get_context_flags(&matches),
+        no_truncate: matches.is_present("no-truncate"),
+        path_style: get_path_style(&matches),
+        theme: theme::Theme::from_env(),
+        group: get_group_flag(&matches),
+        sort: matches
+            .value_of("sort")
+            .and_then(result_handler::SortKey::from_name),
+    };
+
+    if let Some(pick_matches) = matches.subcommand_matches("pick") {
+        let initial = pick_matches.value_of("query").unwrap_or("");
+        if let Err(e) = picker::run(language, &root, filters, initial) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if matches.subcommand_matches("repl").is_some() {
+        if let Err(e) = repl::run(language, &root, filters, &options) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    // Check for filename flag input
-    let filename = get_filename_flag(&matches);
+    if let Some(queries) = get_batch_queries(&matches) {
+        let (results, timing) =
+            batch::run(language, &root, &queries, filters).unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            });
+        let target = open_number.map(|number| get_open_target(&results, number, &options));
+        result_handler::print_results(results, &options, &timing);
+        open_target(target);
+        return;
+    }
+
+    if let Some(index) = can_use_index(&matches)
+        .then(|| load_index(language, &root))
+        .flatten()
+    {
+        let resolve_start = Instant::now();
+        let results = result_handler::collect_results(
+            &index.get_response(),
+            regex,
+            &filters,
+            &mut io::sink(),
+            &mut io::empty(),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("error: Unable to read the index: {}", e);
+            std::process::exit(1);
+        });
+        let timing = result_handler::QueryTiming {
+            server: std::time::Duration::default(),
+            requests: 0,
+            resolve: resolve_start.elapsed(),
+        };
+        let target = open_number.map(|number| get_open_target(&results, number, &options));
+        result_handler::print_results(results, &options, &timing);
+        open_target(target);
+        return;
+    }
 
-    // flags
-    let flags = get_flags(&matches);
+    if matches.is_present("watch") {
+        watch_query(
+            language,
+            &root,
+            regex,
+            position.as_ref(),
+            &filters,
+            &options,
+        );
+        return;
+    }
 
     // start up the server to send/receive
-    let mut server_instance = run_server().expect("Unable to start Rust Lang Server");
-    let rls_stdin = server_instance.stdin.as_mut().unwrap();
-    let mut rls_stdout = server_instance.stdout;
-    let mut rls_stdout_reader = BufReader::new(rls_stdout.take().unwrap());
+    let server_start = Instant::now();
+    let (mut writer, mut reader, server_instance, mut indexing) = connect_server(language, &root);
+    let rls_stdin = writer.as_mut();
+    let rls_stdout_reader = reader.as_mut();
 
     // get init request string
-    let full_msg = lsp_message::init_request();
+    let full_msg = lsp_message::init_request(&root.to_string_lossy());
 
     rls_stdin
-        .write_all(&full_msg.as_bytes())
+        .write_all(full_msg.as_bytes())
         .expect("Error writing json dump to stdin");
 
     notify_initialized(rls_stdin);
 
-    let res_json = get_symbol_response_or_timeout(regex, rls_stdin, &mut rls_stdout_reader);
+    if let Some(changed_matches) = matches.subcommand_matches("changed") {
+        let changes = get_changed_symbols(
+            changed_matches.value_of("range"),
+            language,
+            rls_stdin,
+            rls_stdout_reader,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("error: Unable to read the git diff: {}", e);
+            std::process::exit(1);
+        });
+        let server_time = server_start.elapsed();
+
+        // each symbol goes through the usual filters on its own so it keeps its change
+        let resolve_start = Instant::now();
+        let mut results = Vec::new();
+        for (symbol, change) in changes {
+            let response = json::object! { "result" => json::array![symbol] };
+            let collected = result_handler::collect_changed_results(
+                &response,
+                regex,
+                &filters,
+                change,
+                rls_stdin,
+                rls_stdout_reader,
+            )
+            .unwrap_or_else(|e| {
+                eprintln!("error: Unable to query the server: {}", e);
+                std::process::exit(1);
+            });
+            results.extend(collected);
+        }
+        let timing = result_handler::QueryTiming {
+            server: server_time,
+            requests: 0,
+            resolve: resolve_start.elapsed(),
+        };
+
+        let target = open_number.map(|number| get_open_target(&results, number, &options));
+        result_handler::print_results(results, &options, &timing);
+        stop_server(server_instance);
+        open_target(target);
+        return;
+    }
 
-    result_handler::print_results(
-        &res_json,
-        filename,
-        flags,
+    let (res_json, requests) = get_query_response(
         regex,
+        position.as_ref(),
+        &root,
+        &mut indexing,
         rls_stdin,
-        &mut rls_stdout_reader,
-    );
+        rls_stdout_reader,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("error: Unable to query the server: {}", e);
+        std::process::exit(1);
+    });
+    let server_time = server_start.elapsed();
+
+    let resolve_start = Instant::now();
+    let results =
+        result_handler::collect_results(&res_json, regex, &filters, rls_stdin, rls_stdout_reader)
+            .unwrap_or_else(|e| {
+                eprintln!("error: Unable to query the server: {}", e);
+                std::process::exit(1);
+            });
+    let timing = result_handler::QueryTiming {
+        server: server_time,
+        requests,
+        resolve: resolve_start.elapsed(),
+    };
+
+    let target = open_number.map(|number| get_open_target(&results, number, &options));
+    result_handler::print_results(results, &options, &timing);
+
+    // the editor can take a while, the server isn't needed for it
+    stop_server(server_instance);
+    open_target(target);
 }
 
 #[cfg(test)]
@@ -177,6 +1073,90 @@ mod main_tests {
 
     #[test]
     fn run_server_returns_child_process() {
-        assert!(!run_server().is_err());
+        assert!(run_server(language::Language::Rust, std::path::Path::new(".")).is_ok());
+    }
+
+    #[test]
+    fn kind_values_combine_with_flags() {
+        let yaml = load_yaml!("cli.yml");
+        let matches = App::from_yaml(yaml).get_matches_from(vec![
+            "glsp",
+            "foo",
+            "-s",
+            "--kind",
+            "function,enum-member",
+            "--exclude-kind",
+            "variable",
+        ]);
+        let filter = get_kind_filter(&matches);
+
+        assert!(filter.is_match("Struct"));
+        assert!(filter.is_match("EnumMember"));
+        assert!(!filter.is_match("Method"));
+    }
+
+    #[test]
+    fn context_flags_follow_grep_precedence() {
+        let yaml = load_yaml!("cli.yml");
+        let matches =
+            App::from_yaml(yaml).get_matches_from(vec!["glsp", "foo", "-C", "3", "-A", "1"]);
+        let context = get_context_flags(&matches);
+
+        assert_eq!(3, context.before);
+        assert_eq!(1, context.after);
+    }
+
+    #[test]
+    fn changed_takes_filters_without_regex() {
+        let yaml = load_yaml!("cli.yml");
+        let matches = App::from_yaml(yaml).get_matches_from(vec![
+            "glsp",
+            "changed",
+            "main...HEAD",
+            "--kind",
+            "function",
+        ]);
+        let changed = matches.subcommand_matches("changed").unwrap();
+
+        assert_eq!(Some("main...HEAD"), changed.value_of("range"));
+        let kinds = get_kind_filter(&matches);
+        assert!(kinds.is_match("Function"));
+        assert!(!kinds.is_match("Struct"));
+    }
+
+    #[test]
+    fn dependencies_are_not_answered_from_the_index() {
+        let yaml = load_yaml!("cli.yml");
+        let plain = App::from_yaml(yaml).get_matches_from(vec!["glsp", "foo"]);
+        let deps = App::from_yaml(yaml).get_matches_from(vec!["glsp", "foo", "--include-deps"]);
+        let at = App::from_yaml(yaml).get_matches_from(vec!["glsp", "--at", "src/lib.rs:1:1"]);
+
+        assert!(can_use_index(&plain));
+        assert!(!can_use_index(&deps));
+        assert!(!can_use_index(&at));
+    }
+
+    #[test]
+    fn open_defaults_to_first_result() {
+        let yaml = load_yaml!("cli.yml");
+        let first = App::from_yaml(yaml).get_matches_from(vec!["glsp", "foo", "--open"]);
+        let second = App::from_yaml(yaml).get_matches_from(vec!["glsp", "foo", "--open", "2"]);
+        let none = App::from_yaml(yaml).get_matches_from(vec!["glsp", "foo"]);
+
+        assert_eq!(Some(1), get_open_number(&first));
+        assert_eq!(Some(2), get_open_number(&second));
+        assert_eq!(None, get_open_number(&none));
+    }
+
+    #[test]
+    fn open_before_the_query_leaves_the_query() {
+        let yaml = load_yaml!("cli.yml");
+        let first = App::from_yaml(yaml).get_matches_from(vec!["glsp", "--open", "parse"]);
+        let second = App::from_yaml(yaml).get_matches_from(vec!["glsp", "--open", "2", "parse"]);
+
+        assert_eq!(Some("parse"), get_query(&first));
+        assert_eq!(Some(1), get_open_number(&first));
+        assert_eq!(Some("parse"), get_query(&second));
+        assert_eq!(Some(2), get_open_number(&second));
     }
 }
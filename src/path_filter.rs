@@ -0,0 +1,277 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::{Component, Path, PathBuf};
+
+pub struct PathFilter {
+    root: PathBuf,
+    includes: Option<GlobSet>,
+    excludes: GlobSet,
+}
+
+// Expands a gitignore style pattern into the globs that implement it:
+// a pattern without a slash matches at any depth, a leading slash anchors it
+// to the root, a trailing slash only matches directories, and everything
+// beneath a matched directory matches as well.
+fn expand_pattern(pattern: &str) -> Vec<String> {
+    let dir_only = pattern.ends_with('/');
+    let trimmed = pattern.trim_end_matches('/');
+    let anchored = trimmed.starts_with('/') || trimmed.contains('/');
+    let body = trimmed.trim_start_matches('/');
+
+    let base = if anchored {
+        body.to_string()
+    } else {
+        format!("**/{}", body)
+    };
+
+    if dir_only {
+        vec![format!("{}/**", base)]
+    } else {
+        vec![base.clone(), format!("{}/**", base)]
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        for glob in expand_pattern(pattern) {
+            builder.add(GlobBuilder::new(&glob).literal_separator(true).build()?);
+        }
+    }
+
+    builder.build()
+}
+
+impl PathFilter {
+    // Patterns in includes starting with '!' are treated as excludes, like ripgrep's --glob
+    pub fn new(
+        root: &Path,
+        includes: &[String],
+        excludes: &[String],
+    ) -> Result<PathFilter, globset::Error> {
+        let mut include_patterns: Vec<String> = Vec::new();
+        let mut exclude_patterns: Vec<String> = excludes.to_vec();
+        for pattern in includes {
+            match pattern.strip_prefix('!') {
+                Some(negated) => exclude_patterns.push(negated.to_string()),
+                None => include_patterns.push(pattern.clone()),
+            }
+        }
+
+        let includes = if include_patterns.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&include_patterns)?)
+        };
+
+        Ok(PathFilter {
+            root: root.to_path_buf(),
+            includes,
+            excludes: build_glob_set(&exclude_patterns)?,
+        })
+    }
+
+    // Paths inside the root are matched relative to it, anything else by its absolute path
+    pub fn is_match(&self, path: &Path) -> bool {
+        let subject = path.strip_prefix(&self.root).unwrap_or(path);
+
+        let included = match &self.includes {
+            Some(includes) => includes.is_match(subject),
+            None => true,
+        };
+
+        included && !self.excludes.is_match(subject)
+    }
+}
+
+// A --file value without glob characters
+enum FileValue {
+    // a lone file name like lib.rs, matched wherever the file is
+    Name(PathBuf),
+    // a file or directory of the project, or an absolute one
+    Path(PathBuf),
+}
+
+// Restricts results to the files, directories and globs given with --file
+pub struct FileFilter {
+    values: Vec<FileValue>,
+    globs: Option<PathFilter>,
+}
+
+fn is_glob(value: &str) -> bool {
+    value.contains(['*', '?', '[', '{'])
+}
+
+// Drops "./" and trailing slashes so "./src/net/" compares as "src/net"
+fn normalize(value: &str) -> PathBuf {
+    Path::new(value)
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+impl FileFilter {
+    pub fn new(root: &Path, values: &[String]) -> Result<FileFilter, globset::Error> {
+        let (globs, paths): (Vec<String>, Vec<String>) =
+            values.iter().cloned().partition(|value| is_glob(value));
+
+        let globs = if globs.is_empty() {
+            None
+        } else {
+            Some(PathFilter::new(root, &globs, &[])?)
+        };
+
+        // a single component is a file name unless the project has a directory of that name
+        let values = paths
+            .iter()
+            .map(|value| {
+                let value = normalize(value);
+                let path = root.join(&value);
+                if value.components().count() == 1 && !path.is_dir() {
+                    FileValue::Name(value)
+                } else {
+                    FileValue::Path(path)
+                }
+            })
+            .collect();
+
+        Ok(FileFilter { values, globs })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty() && self.globs.is_none()
+    }
+
+    // Paths are compared by whole components from the project root, so "src/net" matches all
+    // beneath it but not "vendor/x/src/net", and a file name matches "lib.rs" but not "mylib.rs"
+    pub fn is_match(&self, path: &Path) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        let matches_path = self.values.iter().any(|value| match value {
+            FileValue::Name(name) => path.file_name() == Some(name.as_os_str()),
+            FileValue::Path(value) => path.starts_with(value),
+        });
+        let matches_glob = match &self.globs {
+            Some(globs) => globs.is_match(path),
+            None => false,
+        };
+
+        matches_path || matches_glob
+    }
+}
+
+#[cfg(test)]
+mod path_filter_tests {
+
+    use super::*;
+
+    fn get_filter(includes: &[&str], excludes: &[&str]) -> PathFilter {
+        let includes: Vec<String> = includes.iter().map(|p| p.to_string()).collect();
+        let excludes: Vec<String> = excludes.iter().map(|p| p.to_string()).collect();
+        PathFilter::new(Path::new("/home/me/project"), &includes, &excludes).unwrap()
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = get_filter(&[], &[]);
+        assert!(filter.is_match(Path::new("/home/me/project/src/lib.rs")));
+        assert!(filter.is_match(Path::new("/usr/lib/rust/lib.rs")));
+    }
+
+    #[test]
+    fn unanchored_directory_matches_outside_root() {
+        let filter = get_filter(&[], &[".cargo/"]);
+        assert!(!filter.is_match(Path::new("/home/me/.cargo/registry/src/foo/lib.rs")));
+        assert!(filter.is_match(Path::new("/home/me/project/src/cargo.rs")));
+    }
+
+    #[test]
+    fn anchored_pattern_matches_from_root() {
+        let filter = get_filter(&[], &["/target"]);
+        assert!(!filter.is_match(Path::new("/home/me/project/target/debug/build.rs")));
+        assert!(filter.is_match(Path::new("/home/me/project/src/target/mod.rs")));
+    }
+
+    #[test]
+    fn includes_restrict_matches() {
+        let filter = get_filter(&["src/**/tests/*.rs"], &[]);
+        assert!(filter.is_match(Path::new("/home/me/project/src/net/tests/http.rs")));
+        assert!(!filter.is_match(Path::new("/home/me/project/src/net/http.rs")));
+    }
+
+    #[test]
+    fn negated_include_is_an_exclude() {
+        let filter = get_filter(&["!*.md"], &[]);
+        assert!(!filter.is_match(Path::new("/home/me/project/README.md")));
+        assert!(filter.is_match(Path::new("/home/me/project/src/lib.rs")));
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let filter = get_filter(&["src/"], &["tests/"]);
+        assert!(filter.is_match(Path::new("/home/me/project/src/lib.rs")));
+        assert!(!filter.is_match(Path::new("/home/me/project/src/tests/lib.rs")));
+    }
+
+    fn get_file_filter(values: &[&str]) -> FileFilter {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        FileFilter::new(Path::new("/home/me/project"), &values).unwrap()
+    }
+
+    #[test]
+    fn file_name_matches_whole_components() {
+        let filter = get_file_filter(&["lib.rs"]);
+        assert!(filter.is_match(Path::new("/home/me/project/src/lib.rs")));
+        assert!(!filter.is_match(Path::new("/home/me/project/src/mylib.rs")));
+    }
+
+    #[test]
+    fn directory_matches_files_beneath_it() {
+        let filter = get_file_filter(&["./src/net/"]);
+        assert!(filter.is_match(Path::new("/home/me/project/src/net/http.rs")));
+        assert!(filter.is_match(Path::new("/home/me/project/src/net/tcp/stream.rs")));
+        assert!(!filter.is_match(Path::new("/home/me/project/src/network.rs")));
+        assert!(!filter.is_match(Path::new("/home/me/project/vendor/x/src/net/http.rs")));
+    }
+
+    #[test]
+    fn file_name_does_not_match_parent_directories() {
+        let filter = get_file_filter(&["me"]);
+        assert!(!filter.is_match(Path::new("/home/me/project/src/lib.rs")));
+        assert!(filter.is_match(Path::new("/home/me/project/src/me")));
+    }
+
+    #[test]
+    fn project_directory_matches_from_root() {
+        let root = std::env::temp_dir().join(format!("glsp-file-filter-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("tests")).unwrap();
+        let filter = FileFilter::new(&root, &["tests".to_string()]).unwrap();
+
+        assert!(filter.is_match(&root.join("tests").join("http.rs")));
+        assert!(!filter.is_match(&root.join("src").join("tests").join("http.rs")));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn file_globs_match_relative_to_root() {
+        let filter = get_file_filter(&["src/**/tests/*.rs"]);
+        assert!(filter.is_match(Path::new("/home/me/project/src/net/tests/http.rs")));
+        assert!(!filter.is_match(Path::new("/home/me/project/tests/http.rs")));
+    }
+
+    #[test]
+    fn any_file_value_can_match() {
+        let filter = get_file_filter(&["lib.rs", "src/net"]);
+        assert!(filter.is_match(Path::new("/home/me/project/src/lib.rs")));
+        assert!(filter.is_match(Path::new("/home/me/project/src/net/http.rs")));
+        assert!(!filter.is_match(Path::new("/home/me/project/src/main.rs")));
+        assert!(get_file_filter(&[]).is_match(Path::new("/anything.rs")));
+    }
+
+    #[test]
+    fn invalid_glob_is_an_error() {
+        let includes = vec!["src/[".to_string()];
+        assert!(PathFilter::new(Path::new("/"), &includes, &[]).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+
+const FILE_SCHEME: &str = "file://";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PathStyle {
+    Relative,
+    Absolute,
+    Uri,
+}
+
+impl PathStyle {
+    pub fn from_name(style: &str) -> Option<PathStyle> {
+        match style {
+            "relative" => Some(PathStyle::Relative),
+            "absolute" => Some(PathStyle::Absolute),
+            "uri" => Some(PathStyle::Uri),
+            _ => None,
+        }
+    }
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(high), Some(low)) = (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                decoded.push(high * 16 + low);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+// Turns a file:// uri from the server into a local path
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix(FILE_SCHEME).unwrap_or(uri);
+    PathBuf::from(percent_decode(path))
+}
+
+// Turns a local path into the file:// uri the server expects
+pub fn path_to_uri(path: &str) -> String {
+    FILE_SCHEME.to_string() + &percent_encode(path)
+}
+
+pub fn display_path(uri: &str, style: PathStyle, root: &Path) -> String {
+    let path = uri_to_path(uri);
+    match style {
+        PathStyle::Uri => uri.to_string(),
+        PathStyle::Absolute => path.display().to_string(),
+        PathStyle::Relative => match path.strip_prefix(root) {
+            Ok(relative) => relative.display().to_string(),
+            Err(_err) => path.display().to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod paths_tests {
+
+    use super::*;
+
+    #[test]
+    fn uri_is_percent_decoded() {
+        let path = uri_to_path("file:///home/me/my%20project/caf%C3%A9.rs");
+        assert_eq!(PathBuf::from("/home/me/my project/café.rs"), path);
+    }
+
+    #[test]
+    fn malformed_escape_is_kept() {
+        let path = uri_to_path("file:///tmp/100%zz.rs");
+        assert_eq!(PathBuf::from("/tmp/100%zz.rs"), path);
+    }
+
+    #[test]
+    fn path_round_trips_through_uri() {
+        let uri = path_to_uri("/home/me/my project/café.rs");
+        assert_eq!("file:///home/me/my%20project/caf%C3%A9.rs", uri);
+        assert_eq!(
+            PathBuf::from("/home/me/my project/café.rs"),
+            uri_to_path(&uri)
+        );
+    }
+
+    #[test]
+    fn relative_style_strips_project_root() {
+        let root = Path::new("/home/me/project");
+        let uri = "file:///home/me/project/src/lib.rs";
+
+        assert_eq!("src/lib.rs", display_path(uri, PathStyle::Relative, root));
+        assert_eq!(
+            "/home/me/project/src/lib.rs",
+            display_path(uri, PathStyle::Absolute, root)
+        );
+        assert_eq!(uri, display_path(uri, PathStyle::Uri, root));
+    }
+
+    #[test]
+    fn relative_style_keeps_paths_outside_root() {
+        let root = Path::new("/home/me/project");
+        let uri = "file:///usr/lib/rust/lib.rs";
+
+        assert_eq!(
+            "/usr/lib/rust/lib.rs",
+            display_path(uri, PathStyle::Relative, root)
+        );
+    }
+}
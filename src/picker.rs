@@ -0,0 +1,516 @@
+use crate::editor;
+use crate::language::Language;
+use crate::lsp_message;
+use crate::paths::{self, PathStyle};
+use crate::qualified_name::QualifiedName;
+use crate::result_handler::{self, LspResult, ResultFilters};
+use crate::terminal::{self, Key, LineBuffer, RawInput, RawMode};
+use json::JsonValue;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Keys typed within this long of each other are sent as one query
+const DEBOUNCE: Duration = Duration::from_millis(150);
+// How long to wait for a key before looking for the server's answers
+const TICK: Duration = Duration::from_millis(30);
+// While the server is still indexing, empty answers are asked again after this long
+const RETRY: Duration = Duration::from_millis(500);
+// Requests are numbered from here to stay clear of the fixed ids in lsp_message
+const FIRST_REQUEST_ID: u32 = 1000;
+
+// The kinds Tab cycles through, the first shows everything the filters allow
+const KIND_GROUPS: [(&str, &[&str]); 5] = [
+    ("all kinds", &[]),
+    ("functions", &["Function", "Method", "Constructor"]),
+    (
+        "types",
+        &["Struct", "Class", "Enum", "Interface", "TypeParameter"],
+    ),
+    (
+        "values",
+        &["Variable", "Constant", "Field", "Property", "EnumMember"],
+    ),
+    ("modules", &["Module", "Namespace", "Package"]),
+];
+
+const HELP: &str = "enter open  tab kinds  ctrl-r references  esc quit";
+
+// Raw mode on the alternate screen, so the shell is left as it was on the way out
+struct Screen {
+    _raw: RawMode,
+}
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        let raw = RawMode::enable()?;
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[?1049h")?;
+        stdout.flush()?;
+        Ok(Screen { _raw: raw })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[?1049l\x1b[?25h").ok();
+        stdout.flush().ok();
+    }
+}
+
+enum Action {
+    Continue,
+    Open(PathBuf, u32, u32),
+    Quit,
+}
+
+enum View {
+    Symbols,
+    References(String),
+}
+
+// Pads or cuts the text to exactly the width, counted in characters
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text
+        .chars()
+        .map(|c| if c == '\t' { ' ' } else { c })
+        .take(width)
+        .collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
+
+// The first row to show so the selected one stays in view, moving as little as possible
+fn get_scroll_offset(selected: usize, offset: usize, height: usize) -> usize {
+    if height == 0 || selected < offset {
+        selected
+    } else if selected >= offset + height {
+        selected + 1 - height
+    } else {
+        offset
+    }
+}
+
+// Messages from the server, read on their own thread so typing never waits on it
+fn spawn_reader(mut reader: Box<dyn BufRead + Send>) -> Receiver<JsonValue> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(message) = lsp_message::read_message(reader.as_mut()) {
+            let parsed = match json::parse(&message) {
+                Ok(parsed) => parsed,
+                Err(_e) => continue,
+            };
+            if sender.send(parsed).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+struct Picker<'a> {
+    language: Language,
+    root: &'a Path,
+    filters: ResultFilters,
+    query: LineBuffer,
+    // the symbol name sent for the query, without its qualifier
+    name: String,
+    kind_group: usize,
+    // the server's latest answer, kept so changing kinds needs no new request
+    response: JsonValue,
+    results: Vec<LspResult>,
+    view: View,
+    selected: usize,
+    offset: usize,
+    next_id: u32,
+    query_id: Option<u32>,
+    references_id: Option<u32>,
+    send_at: Option<Instant>,
+    indexing: lsp_message::Indexing,
+    status: Option<String>,
+    preview: Option<(String, Vec<String>)>,
+}
+
+impl<'a> Picker<'a> {
+    fn take_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    // Sends the query as typed, cancelling the request for what was typed before
+    fn send_query(&mut self, rls_stdin: &mut dyn Write) -> io::Result<()> {
+        self.send_at = None;
+        if let Some(id) = self.query_id.take() {
+            rls_stdin.write_all(lsp_message::cancel_request(id).as_bytes())?;
+        }
+
+        let text = self.query.text();
+        let query = QualifiedName::parse(&text, self.language, self.root);
+        self.filters.qualifier = query.qualifier;
+        self.name = query.name;
+        if text.trim().is_empty() {
+            self.response = JsonValue::Null;
+            self.collect();
+            return Ok(());
+        }
+
+        let id = self.take_id();
+        rls_stdin.write_all(lsp_message::numbered_symbol_request(&self.name, id).as_bytes())?;
+        self.query_id = Some(id);
+        Ok(())
+    }
+
+    fn send_references(&mut self, rls_stdin: &mut dyn Write) -> io::Result<()> {
+        let target = match self.results.get(self.selected) {
+            Some(target) => target.clone(),
+            None => return Ok(()),
+        };
+
+        let id = self.take_id();
+        let (line, character) = result_handler::get_name_position(&target);
+        let request = lsp_message::numbered_references(target.location(), line, character, id);
+        rls_stdin.write_all(request.as_bytes())?;
+        self.references_id = Some(id);
+        self.view = View::References(target.name().to_string());
+        self.results.clear();
+        Ok(())
+    }
+
+    // Variables are listed by kind, marking them as typed keeps collect_results from
+    // hovering them while the reader thread owns the server's output
+    fn collect(&mut self) {
+        for symbol in self.response["result"].members_mut() {
+            if symbol["kind"] == 13 && symbol["detail"].is_null() {
+                symbol["detail"] = "Variable".into();
+            }
+        }
+
+        let kinds = KIND_GROUPS[self.kind_group].1;
+        self.results = result_handler::collect_results(
+            &self.response,
+            &self.name,
+            &self.filters,
+            &mut io::sink(),
+            &mut io::empty(),
+        )
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|result| kinds.is_empty() || kinds.contains(&result.kind()))
+        .collect();
+        self.selected = 0;
+        self.offset = 0;
+    }
+
+    fn handle_message(&mut self, message: JsonValue) {
+        // requests and notifications from the server carry a method, answers don't
+        if let Some(method) = message["method"].as_str() {
+            if message["id"].is_null() {
+                self.indexing.track_message(method, &message["params"]);
+            }
+            return;
+        }
+        let id = message["id"].as_u32();
+
+        if id.is_some() && id == self.query_id {
+            self.query_id = None;
+            if !message["error"].is_null() {
+                return;
+            }
+            if message["result"].is_empty() && !self.indexing.is_done() {
+                self.send_at = Some(Instant::now() + RETRY);
+                return;
+            }
+            if !message["result"].is_empty() {
+                self.indexing.done();
+            }
+            self.response = message;
+            if let View::Symbols = self.view {
+                self.collect();
+            }
+        } else if id.is_some() && id == self.references_id {
+            self.references_id = None;
+            if let View::References(name) = &self.view {
+                self.results = result_handler::get_location_results(name, "Reference", &message);
+                self.selected = 0;
+                self.offset = 0;
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: Key, rls_stdin: &mut dyn Write) -> io::Result<Action> {
+        self.status = None;
+        match key {
+            Key::Esc | Key::Ctrl('c') | Key::Ctrl('g') => {
+                if let View::References(_) = self.view {
+                    self.view = View::Symbols;
+                    self.collect();
+                } else {
+                    return Ok(Action::Quit);
+                }
+            }
+            Key::Enter => {
+                if let Some(result) = self.results.get(self.selected) {
+                    let path = paths::uri_to_path(result.location());
+                    let range = result.range();
+                    return Ok(Action::Open(
+                        path,
+                        range.start_line + 1,
+                        range.start_character + 1,
+                    ));
+                }
+            }
+            Key::Up | Key::Ctrl('p') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Ctrl('n') => {
+                self.selected = (self.selected + 1).min(self.results.len().saturating_sub(1))
+            }
+            Key::PageUp => self.selected = self.selected.saturating_sub(10),
+            Key::PageDown => {
+                self.selected = (self.selected + 10).min(self.results.len().saturating_sub(1))
+            }
+            Key::Tab => {
+                self.kind_group = (self.kind_group + 1) % KIND_GROUPS.len();
+                if let View::Symbols = self.view {
+                    self.collect();
+                }
+            }
+            Key::Ctrl('r') => self.send_references(rls_stdin)?,
+            key => {
+                if self.query.edit(key) {
+                    self.view = View::Symbols;
+                    self.send_at = Some(Instant::now() + DEBOUNCE);
+                }
+            }
+        }
+        Ok(Action::Continue)
+    }
+
+    // The lines of the selected result's file, read again only when the selection moves files
+    fn get_preview_lines(&mut self) -> &[String] {
+        let location = match self.results.get(self.selected) {
+            Some(result) => result.location().to_string(),
+            None => return &[],
+        };
+        let cached = matches!(&self.preview, Some((cached, _)) if *cached == location);
+        if !cached {
+            let text = fs::read_to_string(paths::uri_to_path(&location)).unwrap_or_default();
+            let lines = text
+                .lines()
+                .map(|line| line.replace('\t', "    "))
+                .collect();
+            self.preview = Some((location, lines));
+        }
+        self.preview.as_ref().map_or(&[], |(_, lines)| lines)
+    }
+
+    fn draw(&mut self, stdout: &mut dyn Write) -> io::Result<()> {
+        let (cols, rows) = terminal::get_size().unwrap_or((80, 24));
+        let height = rows.saturating_sub(2);
+        let left = (cols * 2 / 5).max(20).min(cols);
+        let right = cols.saturating_sub(left + 1);
+        self.offset = get_scroll_offset(self.selected, self.offset, height);
+
+        let mut frame = String::from("\x1b[?25l\x1b[H");
+
+        let waiting = self.query_id.is_some() || self.send_at.is_some();
+        let state = if waiting && !self.indexing.is_done() {
+            "indexing".to_string()
+        } else {
+            match &self.view {
+                View::Symbols => format!(
+                    "{} results, {}",
+                    self.results.len(),
+                    KIND_GROUPS[self.kind_group].0
+                ),
+                View::References(name) => {
+                    format!("{} references to {}", self.results.len(), name)
+                }
+            }
+        };
+        let state_width = state.chars().count().min(cols);
+        frame.push_str(&fit(
+            &format!("> {}", self.query.text()),
+            cols - state_width,
+        ));
+        frame.push_str(&format!("\x1b[2m{}\x1b[0m", fit(&state, state_width)));
+
+        let (symbol_start, symbol_end) = match self.results.get(self.selected) {
+            Some(result) => (result.range().start_line, result.range().end_line),
+            None => (0, 0),
+        };
+        let results: Vec<String> = self
+            .results
+            .iter()
+            .skip(self.offset)
+            .take(height)
+            .map(|result| {
+                format!(
+                    "{} {} {}:{}",
+                    result.name(),
+                    result.kind(),
+                    paths::display_path(result.location(), PathStyle::Relative, self.root),
+                    result.range().start_line + 1
+                )
+            })
+            .collect();
+        let top = (symbol_start as usize).saturating_sub(height / 3);
+        let lines: Vec<String> = self
+            .get_preview_lines()
+            .iter()
+            .skip(top)
+            .take(height)
+            .cloned()
+            .collect();
+
+        for row in 0..height {
+            frame.push_str(&format!("\x1b[{};1H", row + 2));
+            match results.get(row) {
+                Some(text) if self.offset + row == self.selected => {
+                    frame.push_str(&format!("\x1b[7m{}\x1b[0m", fit(text, left)))
+                }
+                Some(text) => frame.push_str(&fit(text, left)),
+                None => frame.push_str(&fit("", left)),
+            }
+            frame.push_str("\x1b[2m│\x1b[0m");
+
+            let number = top + row;
+            let line = lines.get(row).map_or("", |line| line.as_str());
+            let numbered = fit(&format!("{:>5} {}", number + 1, line), right);
+            let in_symbol = (symbol_start as usize..=symbol_end as usize).contains(&number);
+            if lines.get(row).is_none() {
+                frame.push_str(&fit("", right));
+            } else if in_symbol && !self.results.is_empty() {
+                frame.push_str(&format!("\x1b[1m{}\x1b[0m", numbered));
+            } else {
+                frame.push_str(&numbered);
+            }
+        }
+
+        let footer = self.status.as_deref().unwrap_or(HELP);
+        frame.push_str(&format!(
+            "\x1b[{};1H\x1b[2m{}\x1b[0m",
+            rows,
+            fit(footer, cols)
+        ));
+        frame.push_str(&format!("\x1b[1;{}H\x1b[?25h", 3 + self.query.cursor()));
+
+        stdout.write_all(frame.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+// Runs the picker until it is quit, sending the query to one server as it is typed
+pub fn run(
+    language: Language,
+    root: &Path,
+    filters: ResultFilters,
+    initial: &str,
+) -> io::Result<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(io::Error::other("the picker needs a terminal"));
+    }
+
+    // the daemon answers one request before it reads the next, so a $/cancelRequest sent
+    // through it would only arrive after the request it cancels has finished
+    let (mut writer, reader, server_instance, indexing) = crate::start_server(language, root);
+    let rls_stdin = writer.as_mut();
+    rls_stdin.write_all(lsp_message::init_request(&root.to_string_lossy()).as_bytes())?;
+    crate::notify_initialized(rls_stdin);
+    let messages = spawn_reader(reader);
+
+    let mut query = LineBuffer::default();
+    query.set(initial);
+    let mut picker = Picker {
+        language,
+        root,
+        filters,
+        query,
+        name: String::new(),
+        kind_group: 0,
+        response: JsonValue::Null,
+        results: Vec::new(),
+        view: View::Symbols,
+        selected: 0,
+        offset: 0,
+        next_id: FIRST_REQUEST_ID,
+        query_id: None,
+        references_id: None,
+        send_at: Some(Instant::now()),
+        indexing,
+        status: None,
+        preview: None,
+    };
+
+    let mut screen = Some(Screen::enter()?);
+    let mut stdout = io::stdout();
+    let mut input = RawInput;
+    let mut size = None;
+    let mut redraw = true;
+    loop {
+        // redraw after anything changed, including the size of the terminal
+        if redraw || terminal::get_size() != size {
+            size = terminal::get_size();
+            picker.draw(&mut stdout)?;
+        }
+
+        redraw = false;
+        if terminal::wait_for_input(TICK)? {
+            let key = match terminal::read_key(&mut input)? {
+                Some(key) => key,
+                None => break,
+            };
+            match picker.handle_key(key, rls_stdin)? {
+                Action::Continue => {}
+                Action::Quit => break,
+                Action::Open(path, line, column) => {
+                    drop(screen.take());
+                    let opened = editor::open(&path, line, column);
+                    screen = Some(Screen::enter()?);
+                    if let Err(e) = opened {
+                        picker.status = Some(format!("error: {}", e));
+                    }
+                }
+            }
+            redraw = true;
+        }
+
+        while let Ok(message) = messages.try_recv() {
+            picker.handle_message(message);
+            redraw = true;
+        }
+        if picker.send_at.is_some_and(|at| at <= Instant::now()) {
+            picker.send_query(rls_stdin)?;
+            redraw = true;
+        }
+    }
+
+    drop(screen);
+    crate::stop_server(server_instance);
+    Ok(())
+}
+
+#[cfg(test)]
+mod picker_tests {
+
+    use super::*;
+
+    #[test]
+    fn text_is_fit_to_width() {
+        assert_eq!("parse     ", fit("parse", 10));
+        assert_eq!("pars", fit("parse", 4));
+        assert_eq!("a b", fit("a\tb", 3));
+    }
+
+    #[test]
+    fn scroll_keeps_selection_in_view() {
+        assert_eq!(0, get_scroll_offset(3, 0, 10));
+        assert_eq!(3, get_scroll_offset(12, 0, 10));
+        assert_eq!(2, get_scroll_offset(2, 5, 10));
+        assert_eq!(5, get_scroll_offset(7, 5, 10));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::source_context::SymbolRange;
+use json::{object, JsonValue};
+use std::path::PathBuf;
+
+// Lines counted from 0 like the server's ranges, both ends inclusive
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LineRange {
+    start: u32,
+    end: u32,
+}
+
+impl LineRange {
+    // "100:300" counted from 1 as editors do, either end may be left out to leave that side
+    // open. Kept counted from 0 like the server's ranges
+    pub fn parse(value: &str) -> Option<LineRange> {
+        let mut parts = value.splitn(2, ':');
+        let start = parts.next()?.trim();
+        let end = parts.next()?.trim();
+
+        let start = if start.is_empty() {
+            0
+        } else {
+            parse_from_one(start)?
+        };
+        let end = if end.is_empty() {
+            u32::MAX
+        } else {
+            parse_from_one(end)?
+        };
+
+        if start > end {
+            return None;
+        }
+        Some(LineRange { start, end })
+    }
+
+    // A symbol counts when any line of its range falls inside, so a hunk that
+    // only touches the body of a function still reports the function
+    pub fn overlaps(&self, range: SymbolRange) -> bool {
+        range.start_line <= self.end && range.end_line >= self.start
+    }
+}
+
+// A line or column counted from 1, as the number counted from 0
+fn parse_from_one(value: &str) -> Option<u32> {
+    value.trim().parse::<u32>().ok()?.checked_sub(1)
+}
+
+pub struct Position {
+    pub path: PathBuf,
+    pub line: u32,
+    pub character: u32,
+}
+
+impl Position {
+    // "src/lib.rs:12:4" with the line and column counted from 1, split from the right so
+    // paths may contain ':'
+    pub fn parse(value: &str) -> Option<Position> {
+        let mut parts = value.rsplitn(3, ':');
+        let character = parse_from_one(parts.next()?)?;
+        let line = parse_from_one(parts.next()?)?;
+        let path = parts.next()?;
+        if path.is_empty() {
+            return None;
+        }
+
+        Some(Position {
+            path: PathBuf::from(path),
+            line,
+            character,
+        })
+    }
+
+    fn is_within(&self, range: SymbolRange) -> bool {
+        let position = (self.line, self.character);
+        (range.start_line, range.start_character) <= position
+            && position <= (range.end_line, range.end_character)
+    }
+}
+
+pub fn get_range(range: &JsonValue) -> SymbolRange {
+    let start_line = range["start"]["line"].as_u32().unwrap_or(0);
+    let start_character = range["start"]["character"].as_u32().unwrap_or(0);
+    SymbolRange::new(
+        start_line,
+        start_character,
+        range["end"]["line"].as_u32().unwrap_or(start_line),
+        range["end"]["character"]
+            .as_u32()
+            .unwrap_or(start_character),
+    )
+}
+
+// Servers answer documentSymbol either with a flat SymbolInformation list or with nested
+// DocumentSymbols, the nested ones are flattened into the flat shape the rest of glsp reads
+fn flatten_symbols(symbols: &JsonValue, uri: &str, container: &str, flat: &mut Vec<JsonValue>) {
+    for symbol in symbols.members() {
+        if symbol["location"].is_object() {
+            flat.push(symbol.clone());
+            continue;
+        }
+
+        let mut information = object! {
+            "name" => symbol["name"].clone(),
+            "kind" => symbol["kind"].clone(),
+            "location" => object!{
+                "uri" => uri,
+                "range" => symbol["range"].clone()
+            }
+        };
+        if !container.is_empty() {
+            information["containerName"] = container.into();
+        }
+        for key in ["tags", "deprecated"] {
+            if !symbol[key].is_null() {
+                information[key] = symbol[key].clone();
+            }
+        }
+        flat.push(information);
+
+        let name = symbol["name"].as_str().unwrap_or("");
+        flatten_symbols(&symbol["children"], uri, name, flat);
+    }
+}
+
+pub fn get_flat_symbols(response: &JsonValue, uri: &str) -> Vec<JsonValue> {
+    let mut flat: Vec<JsonValue> = Vec::new();
+    flatten_symbols(&response["result"], uri, "", &mut flat);
+    flat
+}
+
+// The symbol with the latest start among those containing the position is the innermost one,
+// returned in a response shaped like a workspace/symbol result
+pub fn find_enclosing_symbol(response: &JsonValue, uri: &str, position: &Position) -> JsonValue {
+    let innermost = get_flat_symbols(response, uri)
+        .into_iter()
+        .map(|symbol| (get_range(&symbol["location"]["range"]), symbol))
+        .filter(|(range, _)| position.is_within(*range))
+        .max_by(|(a, _), (b, _)| {
+            (a.start_line, a.start_character)
+                .cmp(&(b.start_line, b.start_character))
+                .then((b.end_line, b.end_character).cmp(&(a.end_line, a.end_character)))
+        });
+
+    let mut result = JsonValue::new_array();
+    if let Some((_, symbol)) = innermost {
+        result.push(symbol).ok();
+    }
+    object! { "result" => result }
+}
+
+#[cfg(test)]
+mod position_tests {
+
+    use super::*;
+    use json::array;
+
+    #[test]
+    fn line_range_parses_open_ends() {
+        assert_eq!(
+            Some(LineRange {
+                start: 99,
+                end: 299
+            }),
+            LineRange::parse("100:300")
+        );
+        assert_eq!(
+            Some(LineRange {
+                start: 0,
+                end: u32::MAX
+            }),
+            LineRange::parse(":")
+        );
+        assert_eq!(None, LineRange::parse("300:100"));
+        assert_eq!(None, LineRange::parse("100"));
+        assert_eq!(None, LineRange::parse("0:10"));
+    }
+
+    #[test]
+    fn line_range_matches_overlapping_symbols() {
+        let lines = LineRange::parse("11:21").unwrap();
+        assert!(lines.overlaps(SymbolRange::new(5, 0, 12, 1)));
+        assert!(lines.overlaps(SymbolRange::new(20, 0, 20, 4)));
+        assert!(!lines.overlaps(SymbolRange::new(21, 0, 30, 1)));
+    }
+
+    #[test]
+    fn position_splits_from_the_right() {
+        let position = Position::parse("c:/src/lib.rs:12:4").unwrap();
+        assert_eq!(PathBuf::from("c:/src/lib.rs"), position.path);
+        assert_eq!(11, position.line);
+        assert_eq!(3, position.character);
+        assert!(Position::parse("src/lib.rs:12").is_none());
+        assert!(Position::parse("src/lib.rs:0:4").is_none());
+    }
+
+    fn get_range_json(start: u32, end: u32) -> JsonValue {
+        object! {
+            "start" => object!{ "line" => start, "character" => 0 },
+            "end" => object!{ "line" => end, "character" => 1 }
+        }
+    }
+
+    #[test]
+    fn innermost_nested_symbol_is_found() {
+        let response = object! {
+            "result" => array![object!{
+                "name" => "Parser",
+                "kind" => 5,
+                "range" => get_range_json(2, 20),
+                "children" => array![object!{
+                    "name" => "parse",
+                    "kind" => 6,
+                    "range" => get_range_json(8, 12)
+                }]
+            }]
+        };
+        let position = Position::parse("src/lib.rs:11:5").unwrap();
+
+        let found = find_enclosing_symbol(&response, "file:///p/src/lib.rs", &position);
+        assert_eq!("parse", found["result"][0]["name"]);
+        assert_eq!("Parser", found["result"][0]["containerName"]);
+        assert_eq!(
+            "file:///p/src/lib.rs",
+            found["result"][0]["location"]["uri"]
+        );
+    }
+
+    #[test]
+    fn innermost_flat_symbol_is_found() {
+        let response = object! {
+            "result" => array![
+                object!{
+                    "name" => "http",
+                    "kind" => 2,
+                    "location" => object!{ "uri" => "a.rs", "range" => get_range_json(0, 40) }
+                },
+                object!{
+                    "name" => "send",
+                    "kind" => 6,
+                    "location" => object!{ "uri" => "a.rs", "range" => get_range_json(10, 15) }
+                }
+            ]
+        };
+
+        let inside = Position::parse("a.rs:12:1").unwrap();
+        assert_eq!(
+            "send",
+            find_enclosing_symbol(&response, "a.rs", &inside)["result"][0]["name"]
+        );
+
+        let outside = Position::parse("a.rs:51:1").unwrap();
+        assert!(find_enclosing_symbol(&response, "a.rs", &outside)["result"].is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::language::Language;
+use crate::result_handler::strip_generics;
+use std::path::{Path, PathBuf};
+
+// Leading segments that only say where a path starts, not what it names
+const PATH_ROOTS: [&str; 3] = ["crate", "self", "super"];
+// Names written as in Java or Python, e.g. com.acme.User.getName
+const DOTTED_SEPARATOR: &str = ".";
+
+pub struct QualifiedName {
+    pub name: String,
+    pub qualifier: Option<Qualifier>,
+}
+
+// The segments before the symbol's own name, matched against where a result lives
+pub struct Qualifier {
+    segments: Vec<String>,
+    language: Language,
+    root: PathBuf,
+}
+
+impl QualifiedName {
+    // Splits "net::http::Client::send" into the name sent to the server and its qualifier
+    pub fn parse(query: &str, language: Language, root: &Path) -> QualifiedName {
+        let mut segments = split_segments(query, get_separator(query, language));
+
+        let name = match segments.pop() {
+            Some(name) => name,
+            None => return QualifiedName::unqualified(query),
+        };
+        while !segments.is_empty() && PATH_ROOTS.contains(&segments[0].as_str()) {
+            segments.remove(0);
+        }
+
+        let qualifier = if segments.is_empty() {
+            None
+        } else {
+            Some(Qualifier {
+                segments,
+                language,
+                root: root.to_path_buf(),
+            })
+        };
+
+        QualifiedName { name, qualifier }
+    }
+
+    fn unqualified(query: &str) -> QualifiedName {
+        QualifiedName {
+            name: query.to_string(),
+            qualifier: None,
+        }
+    }
+}
+
+// The language's own separator when the query uses it, dots otherwise
+fn get_separator(name: &str, language: Language) -> &'static str {
+    if name.contains(language.separator()) {
+        language.separator()
+    } else {
+        DOTTED_SEPARATOR
+    }
+}
+
+fn split_segments(name: &str, separator: &str) -> Vec<String> {
+    name.split(separator)
+        .map(|segment| segment.trim().to_string())
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+// Reduces a reported container to the type it names,
+// e.g. "impl<T> Display for Parser<T>" becomes "Parser"
+fn normalize_container(container: &str) -> String {
+    let stripped = strip_generics(container);
+    let stripped = stripped.trim();
+    let stripped = match stripped.rfind(" for ") {
+        Some(index) => &stripped[index + " for ".len()..],
+        None => stripped,
+    };
+
+    stripped.trim_start_matches("impl ").trim().to_string()
+}
+
+// Appends the container to the module path, without repeating segments they share
+fn merge_segments(module_path: Vec<String>, container: Vec<String>) -> Vec<String> {
+    let overlap = (0..=container.len().min(module_path.len()))
+        .rev()
+        .find(|k| module_path.ends_with(&container[..*k]))
+        .unwrap_or(0);
+
+    let mut chain = module_path;
+    chain.extend(container.into_iter().skip(overlap));
+    chain
+}
+
+impl Qualifier {
+    // The chain of modules and types enclosing a result, e.g. [net, http, Client]
+    pub fn get_container_chain(&self, path: &Path, container: &str) -> Vec<String> {
+        let module_path = match path.strip_prefix(&self.root) {
+            Ok(relative) => self.language.module_path(relative),
+            Err(_err) => Vec::new(),
+        };
+
+        let container = normalize_container(container);
+        let container = split_segments(&container, get_separator(&container, self.language));
+
+        merge_segments(module_path, container)
+    }
+
+    // The qualifier has to name the innermost enclosing scopes, so Client::send and
+    // http::Client::send both match a method send in impl Client inside net/http.rs
+    pub fn is_match(&self, path: &Path, container: &str) -> bool {
+        self.get_container_chain(path, container)
+            .ends_with(&self.segments)
+    }
+}
+
+#[cfg(test)]
+mod qualified_name_tests {
+
+    use super::*;
+
+    fn parse(query: &str, language: Language) -> QualifiedName {
+        QualifiedName::parse(query, language, Path::new("/p"))
+    }
+
+    #[test]
+    fn plain_query_has_no_qualifier() {
+        let query = parse("send", Language::Rust);
+        assert_eq!("send", query.name);
+        assert!(query.qualifier.is_none());
+    }
+
+    #[test]
+    fn rust_query_splits_on_double_colon() {
+        let query = parse("crate::net::http::Client::send", Language::Rust);
+        assert_eq!("send", query.name);
+        assert_eq!(
+            vec!["net", "http", "Client"],
+            query.qualifier.unwrap().segments
+        );
+    }
+
+    #[test]
+    fn dotted_query_splits_on_dots() {
+        let query = parse("com.acme.User.getName", Language::Rust);
+        assert_eq!("getName", query.name);
+        assert_eq!(
+            vec!["com", "acme", "User"],
+            query.qualifier.unwrap().segments
+        );
+
+        // the language's separator wins when the query uses it
+        let query = parse("net::Client::send", Language::Rust);
+        assert_eq!(vec!["net", "Client"], query.qualifier.unwrap().segments);
+    }
+
+    #[test]
+    fn dotted_qualifier_matches_dotted_container() {
+        let qualifier = parse("acme.User.getName", Language::Rust)
+            .qualifier
+            .unwrap();
+        let path = Path::new("/elsewhere/User.java");
+
+        assert_eq!(
+            vec!["com", "acme", "User"],
+            qualifier.get_container_chain(path, "com.acme.User")
+        );
+        assert!(qualifier.is_match(path, "com.acme.User"));
+        assert!(!qualifier.is_match(path, "com.acme.Group"));
+        // dotted queries match containers reported with the language's separator too
+        assert!(qualifier.is_match(path, "acme::User"));
+    }
+
+    #[test]
+    fn container_is_normalized() {
+        assert_eq!("Parser", normalize_container("impl<T> Parser<T>"));
+        assert_eq!("Parser", normalize_container("impl Display for Parser"));
+        assert_eq!("net::Client", normalize_container("net::Client"));
+    }
+
+    #[test]
+    fn chain_merges_module_path_and_container() {
+        let qualifier = parse("http::Client::send", Language::Rust)
+            .qualifier
+            .unwrap();
+        let path = Path::new("/p/src/net/http.rs");
+
+        assert_eq!(
+            vec!["net", "http", "Client"],
+            qualifier.get_container_chain(path, "impl Client")
+        );
+        assert_eq!(
+            vec!["net", "http"],
+            qualifier.get_container_chain(path, "http")
+        );
+    }
+
+    #[test]
+    fn qualifier_matches_innermost_scopes() {
+        let path = Path::new("/p/src/net/http.rs");
+        let full = parse("net::http::Client::send", Language::Rust)
+            .qualifier
+            .unwrap();
+        let short = parse("Client::send", Language::Rust).qualifier.unwrap();
+        let wrong = parse("Parser::send", Language::Rust).qualifier.unwrap();
+
+        assert!(full.is_match(path, "Client"));
+        assert!(short.is_match(path, "impl Client"));
+        assert!(!wrong.is_match(path, "Client"));
+        assert!(!short.is_match(Path::new("/p/src/lib.rs"), "Parser"));
+    }
+}
@@ -0,0 +1,429 @@
+use crate::index;
+use crate::language::Language;
+use crate::lsp_message;
+use crate::path_filter::FileFilter;
+use crate::paths;
+use crate::position::Position;
+use crate::qualified_name::QualifiedName;
+use crate::result_handler::{self, LspResult, OutputOptions, QueryTiming, ResultFilters};
+use crate::terminal::{self, Key, LineBuffer, RawInput, RawMode};
+use json::JsonValue;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const PROMPT: &str = "glsp> ";
+const HISTORY_LIMIT: usize = 1000;
+
+const HELP: &str = "\
+NAME                 find symbols, qualified names like Client::send work too
+:kind KINDS          only show these kinds, e.g. :kind function,method (:kind alone for all)
+:file FILES          only search these files, directories or globs (:file alone for all)
+:refs [NAME]         references to the result called NAME, or the first, of the last query
+:def FILE:LINE:COL   where the symbol at a position is defined
+:help                show this help
+:quit                leave, as does Ctrl-D";
+
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    Empty,
+    Query(String),
+    Kind(Vec<String>),
+    File(Vec<String>),
+    Refs(Option<String>),
+    Def(String),
+    Help,
+    Quit,
+}
+
+impl Command {
+    // Lines starting with ':' are commands, anything else is a query
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let line = line.trim();
+        let command = match line.strip_prefix(':') {
+            Some(command) => command,
+            None if line.is_empty() => return Ok(Command::Empty),
+            None => return Ok(Command::Query(line.to_string())),
+        };
+
+        let (name, args) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let words: Vec<String> = args
+            .split_whitespace()
+            .map(|word| word.to_string())
+            .collect();
+        match name {
+            "kind" | "k" => Ok(Command::Kind(
+                args.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|kind| !kind.is_empty())
+                    .map(|kind| kind.to_string())
+                    .collect(),
+            )),
+            "file" | "f" => Ok(Command::File(words)),
+            "refs" | "r" => Ok(Command::Refs(words.into_iter().next())),
+            "def" | "d" => match words.into_iter().next() {
+                Some(position) => Ok(Command::Def(position)),
+                None => Err(":def needs a position, e.g. :def src/lib.rs:12:4".to_string()),
+            },
+            "help" | "h" | "?" => Ok(Command::Help),
+            "quit" | "q" | "exit" => Ok(Command::Quit),
+            _ => Err(format!("Unknown command ':{}', :help lists them", name)),
+        }
+    }
+}
+
+// Reads lines with arrow key editing and history when on a terminal, plainly otherwise
+pub struct LineEditor {
+    history: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl LineEditor {
+    pub fn new(path: Option<PathBuf>) -> LineEditor {
+        let history = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(|line| line.to_string()).collect())
+            .unwrap_or_default();
+        LineEditor { history, path }
+    }
+
+    // Kept in memory and appended to the history file, which is trimmed once it grows too long
+    fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(|last| last.as_str()) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.drain(..self.history.len() - HISTORY_LIMIT);
+        }
+
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).ok();
+            }
+            let mut text = self.history.join("\n");
+            text.push('\n');
+            fs::write(path, text).ok();
+        }
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let line = if io::stdin().is_terminal() {
+            self.read_line_raw(prompt)?
+        } else {
+            let mut line = String::new();
+            match io::stdin().lock().read_line(&mut line)? {
+                0 => None,
+                _ => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+            }
+        };
+
+        if let Some(line) = &line {
+            self.add_history(line);
+        }
+        Ok(line)
+    }
+
+    fn read_line_raw(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let _raw = RawMode::enable()?;
+        let mut stdin = RawInput;
+        let mut buffer = LineBuffer::default();
+        // where Up and Down are in the history, the line being typed is kept aside meanwhile
+        let mut browsing = self.history.len();
+        let mut draft = String::new();
+
+        redraw(prompt, &buffer)?;
+        loop {
+            let key = match terminal::read_key(&mut stdin)? {
+                Some(key) => key,
+                None => return Ok(None),
+            };
+            match key {
+                Key::Enter => {
+                    print!("\r\n");
+                    return Ok(Some(buffer.text()));
+                }
+                Key::Ctrl('d') if buffer.is_empty() => {
+                    print!("\r\n");
+                    return Ok(None);
+                }
+                Key::Ctrl('c') => {
+                    print!("^C\r\n");
+                    buffer = LineBuffer::default();
+                    browsing = self.history.len();
+                }
+                Key::Up if browsing > 0 => {
+                    if browsing == self.history.len() {
+                        draft = buffer.text();
+                    }
+                    browsing -= 1;
+                    buffer.set(&self.history[browsing]);
+                }
+                Key::Down if browsing < self.history.len() => {
+                    browsing += 1;
+                    match self.history.get(browsing) {
+                        Some(line) => buffer.set(line),
+                        None => buffer.set(&draft),
+                    }
+                }
+                key => {
+                    buffer.edit(key);
+                }
+            }
+            redraw(prompt, &buffer)?;
+        }
+    }
+}
+
+fn redraw(prompt: &str, buffer: &LineBuffer) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\r\x1b[K{}{}", prompt, buffer.text())?;
+    let behind = buffer.len() - buffer.cursor();
+    if behind > 0 {
+        write!(stdout, "\x1b[{}D", behind)?;
+    }
+    stdout.flush()
+}
+
+fn get_history_path() -> Option<PathBuf> {
+    Some(index::get_cache_dir()?.join("history"))
+}
+
+fn get_response(id: u32, lock: &mut dyn BufRead) -> io::Result<JsonValue> {
+    let check_str = format!("\"id\":{}", id);
+    loop {
+        let message = lsp_message::read_message(lock)?;
+        if message.contains(&check_str) {
+            return json::parse(&message).map_err(io::Error::other);
+        }
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// The identifier under a position, used to name the locations a definition is found at
+fn get_word_at(text: &str, line: u32, character: u32) -> Option<String> {
+    let chars: Vec<char> = text.lines().nth(line as usize)?.chars().collect();
+    let at = (character as usize).min(chars.len());
+    let start = chars[..at]
+        .iter()
+        .rposition(|c| !is_identifier_char(*c))
+        .map_or(0, |index| index + 1);
+    let end = chars[at..]
+        .iter()
+        .position(|c| !is_identifier_char(*c))
+        .map_or(chars.len(), |index| at + index);
+
+    if start == end {
+        return None;
+    }
+    Some(chars[start..end].iter().collect())
+}
+
+// One server session answering the queries typed at the prompt
+struct Session<'a> {
+    language: Language,
+    root: &'a Path,
+    filters: ResultFilters,
+    options: &'a OutputOptions,
+    rls_stdin: &'a mut dyn Write,
+    lock: &'a mut dyn BufRead,
+    indexing: lsp_message::Indexing,
+    last: Vec<LspResult>,
+}
+
+impl<'a> Session<'a> {
+    fn print(&self, results: Vec<LspResult>, server: Duration, requests: u32, resolve: Instant) {
+        let timing = QueryTiming {
+            server,
+            requests,
+            resolve: resolve.elapsed(),
+        };
+        result_handler::print_results(results, self.options, &timing);
+    }
+
+    fn query(&mut self, query: &str) -> io::Result<()> {
+        let query = QualifiedName::parse(query, self.language, self.root);
+        self.filters.qualifier = query.qualifier;
+
+        let server_start = Instant::now();
+        let (response, requests) = crate::get_query_response(
+            &query.name,
+            None,
+            self.root,
+            &mut self.indexing,
+            self.rls_stdin,
+            self.lock,
+        )?;
+        let server_time = server_start.elapsed();
+
+        let resolve_start = Instant::now();
+        let results = result_handler::collect_results(
+            &response,
+            &query.name,
+            &self.filters,
+            self.rls_stdin,
+            self.lock,
+        )?;
+        self.last = results.clone();
+        self.print(results, server_time, requests, resolve_start);
+        Ok(())
+    }
+
+    fn refs(&mut self, name: Option<String>) -> io::Result<()> {
+        let target = match &name {
+            Some(name) => self.last.iter().find(|result| result.name() == name),
+            None => self.last.first(),
+        };
+        let target = match target {
+            Some(target) => target.clone(),
+            None => {
+                eprintln!("error: No result to find references to, run a query first");
+                return Ok(());
+            }
+        };
+
+        let server_start = Instant::now();
+        let (line, character) = result_handler::get_name_position(&target);
+        self.rls_stdin
+            .write_all(lsp_message::references(target.location(), line, character).as_bytes())?;
+        let response = get_response(lsp_message::REFERENCES_REQUEST_ID, self.lock)?;
+
+        let resolve_start = Instant::now();
+        let results = result_handler::get_location_results(target.name(), "Reference", &response);
+        self.print(results, server_start.elapsed(), 1, resolve_start);
+        Ok(())
+    }
+
+    fn def(&mut self, value: &str) -> io::Result<()> {
+        let position = match Position::parse(value) {
+            Some(position) => position,
+            None => {
+                eprintln!(
+                    "error: Invalid position '{}', expected FILE:LINE:COL counted from 1",
+                    value
+                );
+                return Ok(());
+            }
+        };
+        let path = self.root.join(&position.path);
+        let document = paths::path_to_uri(&path.to_string_lossy());
+        let text = fs::read_to_string(&path).unwrap_or_default();
+        let name = get_word_at(&text, position.line, position.character).unwrap_or_default();
+
+        let server_start = Instant::now();
+        self.rls_stdin.write_all(
+            lsp_message::definition(&document, position.line, position.character).as_bytes(),
+        )?;
+        let response = get_response(lsp_message::DEFINITION_REQUEST_ID, self.lock)?;
+
+        let resolve_start = Instant::now();
+        let results = result_handler::get_location_results(&name, "Definition", &response);
+        self.print(results, server_start.elapsed(), 1, resolve_start);
+        Ok(())
+    }
+
+    fn run(&mut self, command: Command) -> io::Result<bool> {
+        match command {
+            Command::Empty => {}
+            Command::Quit => return Ok(false),
+            Command::Help => println!("{}", HELP),
+            Command::Query(query) => self.query(&query)?,
+            Command::Refs(name) => self.refs(name)?,
+            Command::Def(position) => self.def(&position)?,
+            Command::Kind(values) => match self.filters.kinds.with_include(&values) {
+                Ok(kinds) => self.filters.kinds = kinds,
+                Err(e) => eprintln!("error: {}", e),
+            },
+            Command::File(values) => match FileFilter::new(self.root, &values) {
+                Ok(files) => self.filters.files = files,
+                Err(e) => eprintln!("error: Invalid :file glob: {}", e),
+            },
+        }
+        Ok(true)
+    }
+}
+
+// Starts the server once and answers queries from the prompt until it is closed
+pub fn run(
+    language: Language,
+    root: &Path,
+    filters: ResultFilters,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    let (mut writer, mut reader, server_instance, indexing) = crate::connect_server(language, root);
+    writer.write_all(lsp_message::init_request(&root.to_string_lossy()).as_bytes())?;
+    crate::notify_initialized(writer.as_mut());
+
+    let mut session = Session {
+        language,
+        root,
+        filters,
+        options,
+        rls_stdin: writer.as_mut(),
+        lock: reader.as_mut(),
+        indexing,
+        last: Vec::new(),
+    };
+    let mut editor = LineEditor::new(get_history_path());
+
+    while let Some(line) = editor.read_line(PROMPT)? {
+        let keep_going = match Command::parse(&line) {
+            Ok(command) => session.run(command)?,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                true
+            }
+        };
+        if !keep_going {
+            break;
+        }
+    }
+
+    crate::stop_server(server_instance);
+    Ok(())
+}
+
+#[cfg(test)]
+mod repl_tests {
+
+    use super::*;
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!(Ok(Command::Empty), Command::parse("  "));
+        assert_eq!(
+            Ok(Command::Query("Client::send".to_string())),
+            Command::parse("Client::send")
+        );
+        assert_eq!(
+            Ok(Command::Kind(vec![
+                "function".to_string(),
+                "method".to_string()
+            ])),
+            Command::parse(":kind function, method")
+        );
+        assert_eq!(Ok(Command::File(Vec::new())), Command::parse(":file"));
+        assert_eq!(Ok(Command::Refs(None)), Command::parse(":refs"));
+        assert_eq!(
+            Ok(Command::Def("src/lib.rs:3:4".to_string())),
+            Command::parse(":def src/lib.rs:3:4")
+        );
+        assert!(Command::parse(":def").is_err());
+        assert!(Command::parse(":frobnicate").is_err());
+    }
+
+    #[test]
+    fn word_at_position_is_found() {
+        let text = "fn main() {\n    let client = Client::new();\n}";
+        assert_eq!(Some("Client".to_string()), get_word_at(text, 1, 20));
+        assert_eq!(Some("client".to_string()), get_word_at(text, 1, 14));
+        assert_eq!(None, get_word_at(text, 1, 0));
+        assert_eq!(None, get_word_at(text, 5, 0));
+    }
+}
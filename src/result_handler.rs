@@ -1,26 +1,256 @@
+use crate::changed::Change;
+use crate::ignore_filter::IgnoreFilter;
 use crate::lsp_message;
+use crate::path_filter::{FileFilter, PathFilter};
+use crate::paths::{self, PathStyle};
+use crate::position::{self, LineRange};
+use crate::qualified_name::Qualifier;
+use crate::source_context::{self, ContextLines, SymbolRange};
+use crate::table::{self, Cell, Column, Elide, Table};
+use crate::theme::Theme;
 use colored::*;
 use json::JsonValue;
-use std::io::{BufReader, Write};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-struct LspResult {
+#[derive(Clone, PartialEq)]
+pub struct LspResult {
     name: String,
     line_num: u32,
     location: String,
     kind: String,
     data_type: String,
+    range: SymbolRange,
+    container: String,
+    deprecated: bool,
+    change: Option<Change>,
+    query: Option<String>,
 }
 
 impl LspResult {
-    fn new(name: String, kind: String, location: String, line: u32, d_type: String) -> LspResult {
+    fn new(
+        name: String,
+        kind: String,
+        location: String,
+        range: SymbolRange,
+        d_type: String,
+        container: String,
+        deprecated: bool,
+    ) -> LspResult {
         LspResult {
-            name: name,
-            location: location,
-            line_num: line,
-            kind: kind,
+            name,
+            location,
+            line_num: range.start_line,
+            kind,
             data_type: d_type,
+            range,
+            container,
+            deprecated,
+            change: None,
+            query: None,
         }
     }
+
+    pub fn with_change(mut self, change: Change) -> LspResult {
+        self.change = Some(change);
+        self
+    }
+
+    // A removed symbol's range is from the old side of the diff, not the file as it is now
+    pub fn is_removed(&self) -> bool {
+        self.change == Some(Change::Removed)
+    }
+
+    // Tags a result with the batch query that found it
+    pub fn with_query(mut self, query: &str) -> LspResult {
+        self.query = Some(query.to_string());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn range(&self) -> SymbolRange {
+        self.range
+    }
+
+    // the rest are only read by the library's SymbolMatch
+    #[allow(dead_code)]
+    pub fn data_type(&self) -> &str {
+        &self.data_type
+    }
+
+    #[allow(dead_code)]
+    pub fn container(&self) -> &str {
+        &self.container
+    }
+
+    #[allow(dead_code)]
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecated
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SortKey {
+    Path,
+    Line,
+    Name,
+    Kind,
+}
+
+impl SortKey {
+    pub fn from_name(key: &str) -> Option<SortKey> {
+        match key {
+            "path" => Some(SortKey::Path),
+            "line" => Some(SortKey::Line),
+            "name" => Some(SortKey::Name),
+            "kind" => Some(SortKey::Kind),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputMode {
+    Results,
+    Count,
+    Stats,
+}
+
+pub struct QueryTiming {
+    pub server: Duration,
+    pub requests: u32,
+    pub resolve: Duration,
+}
+
+// Kind names as returned by get_symbol_type, an empty include list allows every kind
+pub struct KindFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl KindFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> KindFilter {
+        KindFilter { include, exclude }
+    }
+
+    // Kind names as typed by the user, "all" among the includes allows every kind
+    pub fn parse(include: &[String], exclude: &[String]) -> Result<KindFilter, String> {
+        // hiding every kind would hide every result, which is never what was meant
+        if exclude.iter().any(|kind| kind.to_lowercase() == "all") {
+            return Err(
+                "--exclude-kind does not take 'all', list the kinds to hide instead".to_string(),
+            );
+        }
+
+        let all = include.iter().any(|kind| kind.to_lowercase() == "all");
+        let include = if all {
+            Vec::new()
+        } else {
+            parse_kinds(include)?
+        };
+        Ok(KindFilter::new(include, parse_kinds(exclude)?))
+    }
+
+    // The same excludes with other includes, e.g. a query's own --kind
+    pub fn with_include(&self, include: &[String]) -> Result<KindFilter, String> {
+        let mut filter = KindFilter::parse(include, &[])?;
+        filter.exclude = self.exclude.clone();
+        Ok(filter)
+    }
+
+    pub fn is_match(&self, kind: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|k| k == kind);
+        included && !self.exclude.iter().any(|k| k == kind)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeprecatedFilter {
+    Any,
+    Only,
+    Hide,
+}
+
+impl DeprecatedFilter {
+    pub fn is_match(&self, deprecated: bool) -> bool {
+        match self {
+            DeprecatedFilter::Any => true,
+            DeprecatedFilter::Only => deprecated,
+            DeprecatedFilter::Hide => !deprecated,
+        }
+    }
+}
+
+// Drops generic parameters so "impl<T> Parser<T>" compares as "impl Parser"
+pub(crate) fn strip_generics(container: &str) -> String {
+    let mut depth = 0;
+    container
+        .chars()
+        .filter(|c| {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                _ => return depth == 0,
+            }
+            false
+        })
+        .collect()
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+// A container matches when it ends with the wanted name on an identifier boundary,
+// so "Parser" matches "impl Parser" and "net::Parser" but not "TokenParser"
+pub fn container_matches(container: &str, wanted: &str) -> bool {
+    let container = strip_generics(container);
+    let container = container.trim();
+    if wanted.is_empty() || !container.ends_with(wanted) {
+        return false;
+    }
+
+    let head = &container[..container.len() - wanted.len()];
+    match head.chars().last() {
+        Some(c) => !is_identifier_char(c),
+        None => true,
+    }
+}
+
+pub struct ResultFilters {
+    pub files: FileFilter,
+    pub kinds: KindFilter,
+    pub containers: Vec<String>,
+    pub paths: PathFilter,
+    pub ignore: Option<IgnoreFilter>,
+    pub qualifier: Option<Qualifier>,
+    pub deprecated: DeprecatedFilter,
+    pub lines: Option<LineRange>,
+}
+
+pub struct OutputOptions {
+    pub mode: OutputMode,
+    pub context: ContextLines,
+    pub no_truncate: bool,
+    pub path_style: PathStyle,
+    pub theme: Theme,
+    pub group: bool,
+    pub sort: Option<SortKey>,
 }
 
 struct ResultJson {
@@ -29,18 +259,48 @@ struct ResultJson {
     location: String,
     line: u32,
     character: u32,
+    end_line: u32,
+    end_character: u32,
+    container: String,
+    deprecated: bool,
 }
 
 impl ResultJson {
-    fn new(name: String, kind: u32, location: String, line: u32, character: u32) -> ResultJson {
+    fn new(
+        name: String,
+        kind: u32,
+        location: String,
+        range: SymbolRange,
+        container: String,
+        deprecated: bool,
+    ) -> ResultJson {
         ResultJson {
-            name: name,
-            location: location,
-            line: line,
+            name,
+            location,
+            line: range.start_line,
             kind_int: kind,
-            character: character,
+            character: range.start_character,
+            end_line: range.end_line,
+            end_character: range.end_character,
+            container,
+            deprecated,
         }
     }
+
+    fn range(&self) -> SymbolRange {
+        SymbolRange::new(self.line, self.character, self.end_line, self.end_character)
+    }
+}
+
+const SYMBOL_KIND_COUNT: u32 = 26;
+const DEPRECATED_TAG: u32 = 1;
+
+// Newer servers send SymbolTag.Deprecated in tags, older ones the deprecated flag
+fn is_deprecated(symbol: &JsonValue) -> bool {
+    let tagged = symbol["tags"]
+        .members()
+        .any(|tag| tag.as_u32() == Some(DEPRECATED_TAG));
+    tagged || symbol["deprecated"].as_bool().unwrap_or(false)
 }
 
 fn get_symbol_type(kind: u32) -> String {
@@ -75,44 +335,54 @@ fn get_symbol_type(kind: u32) -> String {
     }
 }
 
-fn get_hover_req_response(
-    reader: &mut BufReader<std::process::ChildStdout>
-) -> json::JsonValue {
-    let mut res: String;
-    let check_str = format!("\"id\":{}", lsp_message::HOVER_REQUEST_ID);
-    loop {
-        let y = match lsp_message::read_message(reader) {
-            Ok(message) => Some(message),
-            Err(_err) => None,
-        };
-        res = y.unwrap();
-        if res.contains(&check_str) {
-            break;
-        }
-    }
+// Matches a user supplied kind such as "enum-member" or "typeparameter" to its symbol type name
+pub fn parse_symbol_kind(name: &str) -> Option<String> {
+    let wanted: String = name
+        .chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .collect::<String>()
+        .to_lowercase();
 
-    return json::parse(&res).unwrap();
+    (1..=SYMBOL_KIND_COUNT)
+        .map(get_symbol_type)
+        .find(|kind| kind.to_lowercase() == wanted)
 }
 
-fn get_heading_str() -> String {
-    format!(
-        "| {0: <25} | {1: <20} | {2: <10} | {3: <10}",
-        "Name".green().bold(),
-        "Type".green().bold(),
-        "Line".green().bold(),
-        "Location".green().bold()
-    )
+pub fn get_symbol_kind_names() -> Vec<String> {
+    (1..=SYMBOL_KIND_COUNT).map(get_symbol_type).collect()
+}
+
+fn parse_kinds(names: &[String]) -> Result<Vec<String>, String> {
+    names
+        .iter()
+        .map(|name| {
+            parse_symbol_kind(name).ok_or_else(|| {
+                format!(
+                    "Unknown kind '{}', expected one of: {}",
+                    name,
+                    get_symbol_kind_names().join(", ")
+                )
+            })
+        })
+        .collect()
 }
 
-fn print_heading() {
-    let heading = get_heading_str();
-    println!("{}", heading);
+fn get_hover_req_response(reader: &mut dyn BufRead) -> io::Result<json::JsonValue> {
+    let check_str = format!("\"id\":{}", lsp_message::HOVER_REQUEST_ID);
+    let res = loop {
+        let message = lsp_message::read_message(reader)?;
+        if message.contains(&check_str) {
+            break message;
+        }
+    };
+
+    json::parse(&res).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
 fn get_response_array_length(json: &JsonValue) -> u64 {
     let ret_len = json["result"].len();
 
-    return ret_len as u64;
+    ret_len as u64
 }
 
 fn get_parsed_result_json(json: &JsonValue, index: u64) -> ResultJson {
@@ -127,89 +397,481 @@ fn get_parsed_result_json(json: &JsonValue, index: u64) -> ResultJson {
         json["result"][index as usize]["location"]["range"]["start"]["character"].to_string();
     let char_num = char_num_str.parse::<u32>().unwrap();
 
-    ResultJson::new(name, type_int, location, line_num, char_num)
+    // the end of the range is optional for our purposes, fall back to the start
+    let end = &json["result"][index as usize]["location"]["range"]["end"];
+    let end_line_num = end["line"].as_u32().unwrap_or(line_num);
+    let end_char_num = end["character"].as_u32().unwrap_or(char_num);
+
+    // servers leave containerName out for top level symbols
+    let container = match json["result"][index as usize]["containerName"].as_str() {
+        Some(container) => container.to_string(),
+        None => String::new(),
+    };
+
+    ResultJson::new(
+        name,
+        type_int,
+        location,
+        SymbolRange::new(line_num, char_num, end_line_num, end_char_num),
+        container,
+        is_deprecated(&json["result"][index as usize]),
+    )
+}
+
+// Servers differ on whether a symbol's range starts at its name or at the start of the item,
+// references have to be asked for on the name itself
+pub fn get_name_position(result: &LspResult) -> (u32, u32) {
+    let range = result.range();
+    let text = fs::read_to_string(paths::uri_to_path(result.location())).unwrap_or_default();
+    let character = text
+        .lines()
+        .nth(range.start_line as usize)
+        .and_then(|line| {
+            let rest: String = line.chars().skip(range.start_character as usize).collect();
+            let found = rest.find(result.name())?;
+            Some(range.start_character + rest[..found].chars().count() as u32)
+        });
+
+    (range.start_line, character.unwrap_or(range.start_character))
+}
+
+pub fn get_hover_type(
+    location: &str,
+    line: u32,
+    character: u32,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<String> {
+    let request = lsp_message::hover(location, line, character);
+    rls_stdin.write_all(request.as_bytes())?;
+    let result = get_hover_req_response(lock)?;
+    Ok(result["result"]["contents"][0]["value"].to_string())
 }
 
 fn read_result(
     json: &JsonValue,
     index: u64,
-    rls_stdin: &mut std::process::ChildStdin,
-    lock: &mut BufReader<std::process::ChildStdout>,
-) -> LspResult {
+    change: Option<Change>,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<LspResult> {
     let parsed_json = get_parsed_result_json(json, index);
-    let data_type: String;
     let kind = get_symbol_type(parsed_json.kind_int);
-    if kind == "Variable" {
-        let request = lsp_message::hover(
+    // a stored index keeps the hover type in "detail" so it need not ask the server again
+    let detail = json["result"][index as usize]["detail"].as_str();
+    // a removed symbol's lines are from the old side, hovering them in the current file would
+    // describe whatever is there now
+    let removed = change == Some(Change::Removed);
+    let data_type = match detail {
+        Some(detail) if kind == "Variable" => detail.to_string(),
+        _ if kind == "Variable" && !removed => get_hover_type(
             &parsed_json.location,
             parsed_json.line,
             parsed_json.character,
-        );
-        rls_stdin
-            .write_all(request.as_bytes())
-            .expect("There was an error sending a message to RLS");
-        let result = get_hover_req_response(lock);
-        data_type = result["result"]["contents"][0]["value"].to_string();
-    } else {
-        data_type = get_symbol_type(parsed_json.kind_int);
-    }
+            rls_stdin,
+            lock,
+        )?,
+        _ => get_symbol_type(parsed_json.kind_int),
+    };
 
-    return LspResult::new(
+    let range = parsed_json.range();
+    let result = LspResult::new(
         parsed_json.name,
         kind,
         parsed_json.location,
-        parsed_json.line,
+        range,
         data_type,
+        parsed_json.container,
+        parsed_json.deprecated,
     );
+    Ok(match change {
+        Some(change) => result.with_change(change),
+        None => result,
+    })
 }
 
-pub fn print_results(
+fn get_results_table(
+    results: &[LspResult],
+    path_style: PathStyle,
+    root: &Path,
+    theme: &Theme,
+    with_location: bool,
+) -> Table {
+    // only spend the width on containers when the server reported any
+    let with_container = results.iter().any(|result| !result.container.is_empty());
+    let with_change = results.iter().any(|result| result.change.is_some());
+    let with_query = results.iter().any(|result| result.query.is_some());
+
+    let mut columns = Vec::new();
+    if with_query {
+        columns.push(Column::new("Query", Elide::End, 5));
+    }
+    if with_change {
+        columns.push(Column::new("Change", Elide::Never, 6));
+    }
+    columns.extend(vec![
+        Column::new("Name", Elide::End, 8),
+        Column::new("Type", Elide::End, 8),
+    ]);
+    if with_container {
+        columns.push(Column::new("Container", Elide::End, 9));
+    }
+    columns.push(Column::new("Line", Elide::Never, 4));
+    if with_location {
+        columns.push(Column::new("Location", Elide::Middle, 16));
+    }
+    let mut table = Table::new(columns);
+
+    for result in results {
+        let name = if result.deprecated {
+            format!("{} (deprecated)", result.name)
+        } else {
+            result.name.clone()
+        };
+        let mut row = Vec::new();
+        if with_query {
+            row.push(Cell::new(result.query.clone().unwrap_or_default()));
+        }
+        if with_change {
+            match result.change {
+                Some(change) => row.push(Cell::colored(
+                    change.name().to_string(),
+                    Some(change.color()),
+                )),
+                None => row.push(Cell::new(String::new())),
+            }
+        }
+        row.push(Cell::new(name));
+        row.push(Cell::colored(
+            result.data_type.clone(),
+            theme.kind_color(&result.kind),
+        ));
+        if with_container {
+            row.push(Cell::new(result.container.clone()));
+        }
+        // shown counted from 1 like editors and --lines do
+        row.push(Cell::new((result.line_num + 1).to_string()));
+        if with_location {
+            row.push(Cell::new(paths::display_path(
+                &result.location,
+                path_style,
+                root,
+            )));
+        }
+        table.push_row(row);
+    }
+
+    table
+}
+
+fn compare_paths(a: &LspResult, b: &LspResult) -> Ordering {
+    paths::uri_to_path(&a.location).cmp(&paths::uri_to_path(&b.location))
+}
+
+fn compare_results(a: &LspResult, b: &LspResult, key: SortKey) -> Ordering {
+    match key {
+        SortKey::Path => compare_paths(a, b).then(a.line_num.cmp(&b.line_num)),
+        SortKey::Line => a.line_num.cmp(&b.line_num),
+        SortKey::Name => a.name.cmp(&b.name),
+        SortKey::Kind => a.kind.cmp(&b.kind).then(a.name.cmp(&b.name)),
+    }
+}
+
+// Stable sort, ties keep the order the server sent them in
+fn sort_results(results: &mut [LspResult], key: SortKey) {
+    results.sort_by(|a, b| compare_results(a, b, key));
+}
+
+fn get_max_width(options: &OutputOptions) -> Option<usize> {
+    if options.no_truncate {
+        None
+    } else {
+        table::get_terminal_width()
+    }
+}
+
+fn print_result_context(result: &LspResult, options: &OutputOptions) {
+    if !options.context.is_empty() && !result.is_removed() {
+        source_context::print_context(
+            &paths::uri_to_path(&result.location),
+            &result.range,
+            &options.context,
+            options.theme.highlight,
+        );
+    }
+}
+
+fn print_table(results: &[LspResult], options: &OutputOptions) {
+    let root = PathBuf::from(lsp_message::get_cur_working_dir());
+    let table = get_results_table(results, options.path_style, &root, &options.theme, true);
+    let widths = table.get_column_widths(get_max_width(options));
+
+    println!("{}", table.get_heading_str(&widths, options.theme.heading));
+    for (i, result) in results.iter().enumerate() {
+        println!("{}", table.get_row_str(i, &widths));
+        print_result_context(result, options);
+    }
+}
+
+// Prints each file once as a heading with its hits beneath, expects results sorted by path
+fn print_grouped(results: &[LspResult], options: &OutputOptions) {
+    let root = PathBuf::from(lsp_message::get_cur_working_dir());
+    let table = get_results_table(results, options.path_style, &root, &options.theme, false);
+    let widths = table.get_column_widths(get_max_width(options));
+
+    let mut current_location: Option<&str> = None;
+    for (i, result) in results.iter().enumerate() {
+        if current_location != Some(result.location.as_str()) {
+            if current_location.is_some() {
+                println!();
+            }
+            let path = paths::display_path(&result.location, options.path_style, &root);
+            println!("{}", path.color(options.theme.heading).bold());
+            current_location = Some(&result.location);
+        }
+
+        println!("{}", table.get_row_str(i, &widths));
+        print_result_context(result, options);
+    }
+}
+
+// Counts results per key, ordered by the key
+fn count_by<F>(results: &[LspResult], key: F) -> Vec<(String, usize)>
+where
+    F: Fn(&LspResult) -> String,
+{
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for result in results {
+        *counts.entry(key(result)).or_insert(0) += 1;
+    }
+
+    counts.into_iter().collect()
+}
+
+fn get_directory(result: &LspResult, path_style: PathStyle, root: &Path) -> String {
+    let path = paths::display_path(&result.location, path_style, root);
+    match Path::new(&path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.display().to_string(),
+        _ => ".".to_string(),
+    }
+}
+
+fn print_counts(results: &[LspResult], options: &OutputOptions) {
+    let root = PathBuf::from(lsp_message::get_cur_working_dir());
+    let counts = count_by(results, |result| {
+        paths::display_path(&result.location, options.path_style, &root)
+    });
+
+    for (path, count) in counts {
+        println!("{}:{}", path.color(options.theme.heading).bold(), count);
+    }
+}
+
+fn print_stats_section(title: &str, counts: &[(String, usize)], width: usize, theme: &Theme) {
+    println!("{}", title.color(theme.heading).bold());
+    for (key, count) in counts {
+        let padded = format!("{0: <1$}", key, width);
+        match theme.kind_color(key) {
+            Some(color) => println!("  {}  {: >5}", padded.color(color), count),
+            None => println!("  {}  {: >5}", padded, count),
+        }
+    }
+}
+
+fn print_stats(results: &[LspResult], options: &OutputOptions, timing: &QueryTiming) {
+    let root = PathBuf::from(lsp_message::get_cur_working_dir());
+    let kinds = count_by(results, |result| result.kind.clone());
+    let directories = count_by(results, |result| {
+        get_directory(result, options.path_style, &root)
+    });
+    let files = count_by(results, |result| result.location.clone());
+
+    let totals = vec![
+        ("matches".to_string(), results.len()),
+        ("files".to_string(), files.len()),
+    ];
+
+    // line every section's counts up on the same column
+    let width = kinds
+        .iter()
+        .chain(directories.iter())
+        .chain(totals.iter())
+        .map(|(key, _)| key.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    print_stats_section("Kinds", &kinds, width, &options.theme);
+    print_stats_section("Directories", &directories, width, &options.theme);
+    print_stats_section("Totals", &totals, width, &options.theme);
+    println!("{}", "Server".color(options.theme.heading).bold());
+    println!(
+        "  {0: <1$}  {2:.3}s over {3} request(s)",
+        "query",
+        width,
+        timing.server.as_secs_f64(),
+        timing.requests
+    );
+    println!(
+        "  {0: <1$}  {2:.3}s",
+        "resolve",
+        width,
+        timing.resolve.as_secs_f64()
+    );
+}
+
+// Puts results in the order they are listed in. Grouped results stay in path order, with
+// --sort ordering the hits within each file
+pub fn order_results(results: &mut [LspResult], options: &OutputOptions) {
+    if options.group {
+        let key = options.sort.unwrap_or(SortKey::Line);
+        results.sort_by(|a, b| compare_paths(a, b).then(compare_results(a, b, key)));
+    } else if let Some(key) = options.sort {
+        sort_results(results, key);
+    }
+}
+
+pub fn print_results(mut results: Vec<LspResult>, options: &OutputOptions, timing: &QueryTiming) {
+    match options.mode {
+        OutputMode::Count => print_counts(&results, options),
+        OutputMode::Stats => print_stats(&results, options, timing),
+        OutputMode::Results => {
+            order_results(&mut results, options);
+            if options.group {
+                print_grouped(&results, options);
+            } else {
+                print_table(&results, options);
+            }
+        }
+    }
+}
+
+// References and definitions come back as locations, shown under the name that was looked up
+// with the label in place of a kind. Servers may answer with one Location, a list of them or
+// LocationLinks
+pub fn get_location_results(name: &str, label: &str, response: &JsonValue) -> Vec<LspResult> {
+    let locations: Vec<&JsonValue> = if response["result"].is_array() {
+        response["result"].members().collect()
+    } else if response["result"].is_object() {
+        vec![&response["result"]]
+    } else {
+        Vec::new()
+    };
+
+    locations
+        .into_iter()
+        .map(|location| {
+            let (uri, range) = if location["targetUri"].is_string() {
+                (&location["targetUri"], &location["targetSelectionRange"])
+            } else {
+                (&location["uri"], &location["range"])
+            };
+            LspResult::new(
+                name.to_string(),
+                label.to_string(),
+                uri.to_string(),
+                position::get_range(range),
+                label.to_string(),
+                String::new(),
+                false,
+            )
+        })
+        .collect()
+}
+
+pub fn collect_results(
     json: &JsonValue,
-    filename: String,
-    flags: Vec<String>,
     regex: &str,
-    rls_stdin: &mut std::process::ChildStdin,
-    lock: &mut BufReader<std::process::ChildStdout>,
-) {
+    filters: &ResultFilters,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<Vec<LspResult>> {
+    collect(json, regex, filters, None, rls_stdin, lock)
+}
+
+// Like collect_results for symbols a diff touched, each result tagged with the change
+pub fn collect_changed_results(
+    json: &JsonValue,
+    regex: &str,
+    filters: &ResultFilters,
+    change: Change,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<Vec<LspResult>> {
+    collect(json, regex, filters, Some(change), rls_stdin, lock)
+}
+
+fn collect(
+    json: &JsonValue,
+    regex: &str,
+    filters: &ResultFilters,
+    change: Option<Change>,
+    rls_stdin: &mut dyn Write,
+    lock: &mut dyn BufRead,
+) -> io::Result<Vec<LspResult>> {
     let max_index = get_response_array_length(json);
-    print_heading();
+    let mut results: Vec<LspResult> = Vec::new();
 
     // loop through each result in the array
     for i in 0..max_index {
         let location: String = json["result"][i as usize]["location"]["uri"].to_string();
 
-        // Skip standard lib files
-        if location.contains(".rustup") || location.contains(".cargo") {
+        // Skip excluded paths before asking the server anything more about them
+        let path = paths::uri_to_path(&location);
+        if !filters.paths.is_match(&path) || !filters.files.is_match(&path) {
             continue;
         }
+        if let Some(ignore) = &filters.ignore {
+            if ignore.is_ignored(&path) {
+                continue;
+            }
+        }
 
-        let query_res = read_result(json, i, rls_stdin, lock);
-
-        // flag + optional checks
-        let matches_optional_file = filename == "" || query_res.location.contains(&filename);
-        let toolchain =
-            query_res.location.contains(".rustup") || query_res.location.contains(".cargo");
+        if let Some(lines) = &filters.lines {
+            if !lines.overlaps(get_parsed_result_json(json, i).range()) {
+                continue;
+            }
+        }
 
-        if (flags.contains(&query_res.kind)
-            || flags.len() == 0
-            || flags.contains(&"All".to_string()))
-            && !toolchain
+        if !filters
+            .deprecated
+            .is_match(is_deprecated(&json["result"][i as usize]))
         {
-            if matches_optional_file && query_res.name.contains(regex) {
-                let mut name = query_res.name.clone();
-                if name.len() > 25 {
-                    name.truncate(25);
-                }
-                println!(
-                    "| {0: <25} | {1: <20} | {2: <10} | {3: <10}",
-                    name,
-                    query_res.data_type,
-                    query_res.line_num,
-                    query_res.location.replace("file://", "")
-                );
+            continue;
+        }
+
+        // the kind is known up front, so filtered out variables are never hovered
+        let kind_int = json["result"][i as usize]["kind"].as_u32().unwrap_or(0);
+        if !filters.kinds.is_match(&get_symbol_type(kind_int)) {
+            continue;
+        }
+
+        let container = json["result"][i as usize]["containerName"]
+            .as_str()
+            .unwrap_or("");
+        if let Some(qualifier) = &filters.qualifier {
+            if !qualifier.is_match(&path, container) {
+                continue;
             }
         }
+
+        if !filters.containers.is_empty() {
+            let matches_container = filters
+                .containers
+                .iter()
+                .any(|wanted| container_matches(container, wanted));
+            if !matches_container {
+                continue;
+            }
+        }
+
+        let query_res = read_result(json, i, change, rls_stdin, lock)?;
+
+        if query_res.name.contains(regex) {
+            results.push(query_res);
+        }
     }
+
+    Ok(results)
 }
 
 // ------------------- UNIT TESTS --------------------
@@ -225,15 +887,343 @@ mod result_handler_tests {
         assert_eq!("Unknown", get_symbol_type(99));
     }
 
+    #[test]
+    fn every_symbol_kind_can_be_parsed() {
+        for kind in get_symbol_kind_names() {
+            assert_eq!(Some(kind.clone()), parse_symbol_kind(&kind.to_lowercase()));
+        }
+        assert_eq!(26, get_symbol_kind_names().len());
+    }
+
+    #[test]
+    fn symbol_kind_parsing_ignores_separators() {
+        assert_eq!(
+            Some("EnumMember".to_string()),
+            parse_symbol_kind("enum-member")
+        );
+        assert_eq!(
+            Some("TypeParameter".to_string()),
+            parse_symbol_kind("type_parameter")
+        );
+        assert_eq!(None, parse_symbol_kind("unknown"));
+    }
+
+    #[test]
+    fn kind_filter_includes_and_excludes() {
+        let filter = KindFilter::new(
+            vec!["Function".to_string(), "Method".to_string()],
+            vec!["Method".to_string()],
+        );
+        assert!(filter.is_match("Function"));
+        assert!(!filter.is_match("Method"));
+        assert!(!filter.is_match("Struct"));
+
+        let exclude_only = KindFilter::new(Vec::new(), vec!["Variable".to_string()]);
+        assert!(exclude_only.is_match("Struct"));
+        assert!(!exclude_only.is_match("Variable"));
+    }
+
+    #[test]
+    fn kind_filter_parses_user_names() {
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        let filter =
+            KindFilter::parse(&names(&["function", "all"]), &names(&["enum-member"])).unwrap();
+        assert!(filter.is_match("Struct"));
+        assert!(!filter.is_match("EnumMember"));
+
+        let query = filter
+            .with_include(&names(&["enum_member", "method"]))
+            .unwrap();
+        assert!(query.is_match("Method"));
+        assert!(!query.is_match("EnumMember"));
+
+        assert!(KindFilter::parse(&names(&["unknown"]), &[]).is_err());
+        assert!(KindFilter::parse(&[], &names(&["All"])).is_err());
+    }
+
     #[test]
     fn heading_is_properly_formatted() {
-        let heading = get_heading_str();
+        let theme = Theme::default();
+        let table = get_results_table(&[], PathStyle::Relative, Path::new("/"), &theme, true);
+        let heading = table.get_heading_str(&table.get_column_widths(None), theme.heading);
         assert!(heading.contains("Name"));
         assert!(heading.contains("Type"));
         assert!(heading.contains("Line"));
         assert!(heading.contains("Location"));
     }
 
+    fn get_test_result(name: &str, kind: &str, location: &str, line: u32) -> LspResult {
+        LspResult::new(
+            name.to_string(),
+            kind.to_string(),
+            location.to_string(),
+            SymbolRange::new(line, 0, line, 0),
+            kind.to_string(),
+            String::new(),
+            false,
+        )
+    }
+
+    fn get_test_results() -> Vec<LspResult> {
+        vec![
+            get_test_result("parse", "Method", "file:///p/src/lib.rs", 11),
+            get_test_result("Parser", "Struct", "file:///p/src/lib.rs", 2),
+            get_test_result("parse_url", "Function", "file:///p/src/http.rs", 15),
+        ]
+    }
+
+    #[test]
+    fn sort_by_path_orders_lines_within_file() {
+        let mut results = get_test_results();
+        sort_results(&mut results, SortKey::Path);
+
+        let order: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(vec!["parse_url", "Parser", "parse"], order);
+    }
+
+    #[test]
+    fn sort_by_name_and_kind() {
+        let mut results = get_test_results();
+        sort_results(&mut results, SortKey::Name);
+        assert_eq!("Parser", results[0].name);
+
+        sort_results(&mut results, SortKey::Kind);
+        assert_eq!("Function", results[0].kind);
+        assert_eq!("Struct", results[2].kind);
+    }
+
+    #[test]
+    fn grouped_results_are_sorted_within_each_file() {
+        let mut options = OutputOptions {
+            mode: OutputMode::Results,
+            context: ContextLines::new(0, 0),
+            no_truncate: false,
+            path_style: PathStyle::Relative,
+            theme: Theme::default(),
+            group: true,
+            sort: Some(SortKey::Name),
+        };
+        let mut results = get_test_results();
+        results.push(get_test_result(
+            "from_str",
+            "Method",
+            "file:///p/src/lib.rs",
+            20,
+        ));
+        order_results(&mut results, &options);
+
+        let order: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(vec!["parse_url", "Parser", "from_str", "parse"], order);
+
+        options.sort = None;
+        order_results(&mut results, &options);
+        let order: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(vec!["parse_url", "Parser", "parse", "from_str"], order);
+    }
+
+    #[test]
+    fn count_by_groups_and_orders_keys() {
+        let results = get_test_results();
+        let counts = count_by(&results, |result| result.location.clone());
+
+        assert_eq!(
+            vec![
+                ("file:///p/src/http.rs".to_string(), 1),
+                ("file:///p/src/lib.rs".to_string(), 2)
+            ],
+            counts
+        );
+    }
+
+    #[test]
+    fn directory_is_relative_to_root() {
+        let root = Path::new("/p");
+        let result = get_test_result("main", "Function", "file:///p/main.rs", 0);
+
+        assert_eq!(
+            "src",
+            get_directory(&get_test_results()[0], PathStyle::Relative, root)
+        );
+        assert_eq!(".", get_directory(&result, PathStyle::Relative, root));
+    }
+
+    #[test]
+    fn container_name_is_parsed() {
+        let test_msg = object! {
+            "result" => array!{
+                object! {
+                    "name" => "send",
+                    "kind" => 6,
+                    "containerName" => "impl Client",
+                    "location" => object! {
+                        "uri" => "http.rs",
+                        "range" => object! {
+                            "start" => object!{
+                                "line" => 10,
+                                "character" => 11,
+                            },
+                        }
+                    },
+                }
+            }
+        };
+
+        assert_eq!(
+            "impl Client",
+            get_parsed_result_json(&test_msg, 0).container
+        );
+    }
+
+    #[test]
+    fn container_matches_on_identifier_boundary() {
+        assert!(container_matches("impl Parser", "Parser"));
+        assert!(container_matches("net::http::Client", "Client"));
+        assert!(container_matches("net::http::Client", "http::Client"));
+        assert!(container_matches("impl<T> Parser<T>", "Parser"));
+        assert!(container_matches("UserService", "UserService"));
+        assert!(!container_matches("TokenParser", "Parser"));
+        assert!(!container_matches("", "Parser"));
+    }
+
+    #[test]
+    fn deprecated_is_read_from_tags_or_flag() {
+        assert!(is_deprecated(&object! { "tags" => array![1] }));
+        assert!(is_deprecated(&object! { "deprecated" => true }));
+        assert!(!is_deprecated(&object! { "tags" => array![] }));
+        assert!(!is_deprecated(&object! { "name" => "send" }));
+    }
+
+    #[test]
+    fn deprecated_filter_keeps_or_hides() {
+        assert!(DeprecatedFilter::Any.is_match(true));
+        assert!(DeprecatedFilter::Only.is_match(true));
+        assert!(!DeprecatedFilter::Only.is_match(false));
+        assert!(!DeprecatedFilter::Hide.is_match(true));
+    }
+
+    #[test]
+    fn deprecated_results_are_marked() {
+        let theme = Theme::default();
+        let mut results = get_test_results();
+        results[0].deprecated = true;
+        let table = get_results_table(&results, PathStyle::Relative, Path::new("/p"), &theme, true);
+        let widths = table.get_column_widths(None);
+        assert!(table.get_row_str(0, &widths).contains("parse (deprecated)"));
+        assert!(!table.get_row_str(1, &widths).contains("(deprecated)"));
+    }
+
+    #[test]
+    fn change_column_leads_when_changes_are_known() {
+        let theme = Theme::default();
+        let results: Vec<LspResult> = get_test_results()
+            .into_iter()
+            .map(|result| result.with_change(Change::Removed))
+            .collect();
+        let table = get_results_table(&results, PathStyle::Relative, Path::new("/p"), &theme, true);
+        let widths = table.get_column_widths(None);
+        assert_eq!(5, widths.len());
+        assert!(table.get_row_str(0, &widths).contains("removed"));
+    }
+
+    #[test]
+    fn query_column_tags_batch_results() {
+        let theme = Theme::default();
+        let results: Vec<LspResult> = get_test_results()
+            .into_iter()
+            .map(|result| result.with_query("pars"))
+            .collect();
+        let table = get_results_table(&results, PathStyle::Relative, Path::new("/p"), &theme, true);
+        let widths = table.get_column_widths(None);
+        assert_eq!(5, widths.len());
+        assert!(table.get_row_str(0, &widths).starts_with("| pars "));
+    }
+
+    #[test]
+    fn container_column_only_shown_when_reported() {
+        let theme = Theme::default();
+        let mut results = get_test_results();
+        let table = get_results_table(&results, PathStyle::Relative, Path::new("/p"), &theme, true);
+        assert_eq!(4, table.get_column_widths(None).len());
+
+        results[0].container = "impl Parser".to_string();
+        let table = get_results_table(&results, PathStyle::Relative, Path::new("/p"), &theme, true);
+        let widths = table.get_column_widths(None);
+        assert_eq!(5, widths.len());
+        assert!(table.get_row_str(0, &widths).contains("impl Parser"));
+    }
+
+    #[test]
+    fn grouped_table_has_no_location_column() {
+        let theme = Theme::default();
+        let results = get_test_results();
+        let table = get_results_table(
+            &results,
+            PathStyle::Relative,
+            Path::new("/p"),
+            &theme,
+            false,
+        );
+        let widths = table.get_column_widths(None);
+
+        assert_eq!(3, widths.len());
+        assert!(!table.get_row_str(0, &widths).contains("lib.rs"));
+    }
+
+    #[test]
+    fn table_counts_lines_from_one() {
+        let theme = Theme::default();
+        let results = get_test_results();
+        let table = get_results_table(&results, PathStyle::Relative, Path::new("/p"), &theme, true);
+        let widths = table.get_column_widths(None);
+        let row = table.get_row_str(0, &widths);
+
+        assert!(row.contains("12"));
+        assert!(!row.contains("11"));
+    }
+
+    #[test]
+    fn removed_variables_are_not_hovered() {
+        let root = Path::new("/p");
+        let filters = ResultFilters {
+            files: FileFilter::new(root, &[]).unwrap(),
+            kinds: KindFilter::new(Vec::new(), Vec::new()),
+            containers: Vec::new(),
+            paths: PathFilter::new(root, &[], &[]).unwrap(),
+            ignore: None,
+            qualifier: None,
+            deprecated: DeprecatedFilter::Any,
+            lines: None,
+        };
+        let response = object! {
+            "result" => array![object!{
+                "name" => "limit",
+                "kind" => 13,
+                "location" => object!{
+                    "uri" => "file:///p/src/lib.rs",
+                    "range" => object!{
+                        "start" => object!{ "line" => 3, "character" => 4 },
+                        "end" => object!{ "line" => 3, "character" => 9 }
+                    }
+                }
+            }]
+        };
+
+        // hovering would fail on the empty reader
+        let results = collect_changed_results(
+            &response,
+            "limit",
+            &filters,
+            Change::Removed,
+            &mut io::sink(),
+            &mut io::empty(),
+        )
+        .unwrap();
+
+        assert_eq!("Variable", results[0].data_type());
+        assert!(results[0].is_removed());
+    }
+
     #[test]
     fn get_response_array_length_returns_correct_length() {
         let test_msg = object! {
@@ -252,6 +1242,7 @@ mod result_handler_tests {
     }
 
     #[test]
+    #[allow(non_snake_case)]
     fn response_properly_parsed_to_Result_Json() {
         let test_msg = object! {
             "result" => array!{
@@ -278,5 +1269,71 @@ mod result_handler_tests {
         assert!(result_json.location == "FooBar.rs");
         assert!(result_json.line == 420);
         assert!(result_json.character == 22);
+        assert!(result_json.end_line == 420);
+        assert!(result_json.end_character == 22);
+    }
+
+    #[test]
+    fn response_range_end_is_parsed() {
+        let test_msg = object! {
+            "result" => array!{
+                object! {
+                    "name" => "test",
+                    "kind" => 12,
+                    "location" => object! {
+                        "uri" => "FooBar.rs",
+                        "range" => object! {
+                            "start" => object!{
+                                "line" => 4,
+                                "character" => 7,
+                            },
+                            "end" => object!{
+                                "line" => 4,
+                                "character" => 11,
+                            },
+                        }
+                    },
+                }
+            }
+        };
+
+        let result_json = get_parsed_result_json(&test_msg, 0);
+
+        assert!(result_json.end_line == 4);
+        assert!(result_json.end_character == 11);
+    }
+
+    #[test]
+    fn locations_and_location_links_become_results() {
+        let range = object! {
+            "start" => object!{ "line" => 3, "character" => 4 },
+            "end" => object!{ "line" => 3, "character" => 9 }
+        };
+        let response = object! {
+            "result" => array![
+                object!{ "uri" => "file:///p/src/lib.rs", "range" => range.clone() },
+                object!{
+                    "targetUri" => "file:///p/src/main.rs",
+                    "targetRange" => range.clone(),
+                    "targetSelectionRange" => range.clone()
+                }
+            ]
+        };
+
+        let results = get_location_results("parse", "Reference", &response);
+        assert_eq!(2, results.len());
+        assert_eq!("file:///p/src/lib.rs", results[0].location());
+        assert_eq!("file:///p/src/main.rs", results[1].location());
+        assert_eq!("Reference", results[1].kind);
+        assert_eq!(3, results[1].range().start_line);
+
+        let single = object! {
+            "result" => object!{ "uri" => "file:///p/src/lib.rs", "range" => object!{} }
+        };
+        assert_eq!(
+            1,
+            get_location_results("parse", "Definition", &single).len()
+        );
+        assert!(get_location_results("parse", "Definition", &object! {}).is_empty());
     }
 }
@@ -0,0 +1,179 @@
+use colored::*;
+use std::fs;
+use std::path::Path;
+
+pub struct ContextLines {
+    pub before: u32,
+    pub after: u32,
+}
+
+impl ContextLines {
+    pub fn new(before: u32, after: u32) -> ContextLines {
+        ContextLines { before, after }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before == 0 && self.after == 0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct SymbolRange {
+    pub start_line: u32,
+    pub start_character: u32,
+    pub end_line: u32,
+    pub end_character: u32,
+}
+
+impl SymbolRange {
+    pub fn new(
+        start_line: u32,
+        start_character: u32,
+        end_line: u32,
+        end_character: u32,
+    ) -> SymbolRange {
+        SymbolRange {
+            start_line,
+            start_character,
+            end_line,
+            end_character,
+        }
+    }
+}
+
+// Returns the (line number, text) pairs surrounding the range, clamped to the file
+fn get_context_window(
+    source: &str,
+    range: &SymbolRange,
+    context: &ContextLines,
+) -> Vec<(u32, String)> {
+    let first = range.start_line.saturating_sub(context.before);
+    let last = range.end_line.saturating_add(context.after);
+
+    source
+        .lines()
+        .enumerate()
+        .map(|(num, text)| (num as u32, text.to_string()))
+        .filter(|(num, _)| *num >= first && *num <= last)
+        .collect()
+}
+
+// Splits a line into the text before, inside and after the symbol's range
+fn split_highlight(line_num: u32, text: &str, range: &SymbolRange) -> (String, String, String) {
+    if line_num < range.start_line || line_num > range.end_line {
+        return (text.to_string(), String::new(), String::new());
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let start = if line_num == range.start_line {
+        (range.start_character as usize).min(chars.len())
+    } else {
+        0
+    };
+    let end = if line_num == range.end_line {
+        (range.end_character as usize).min(chars.len()).max(start)
+    } else {
+        chars.len()
+    };
+
+    (
+        chars[..start].iter().collect(),
+        chars[start..end].iter().collect(),
+        chars[end..].iter().collect(),
+    )
+}
+
+fn get_context_line_str(line_num: u32, text: &str, range: &SymbolRange, color: Color) -> String {
+    let in_range = line_num >= range.start_line && line_num <= range.end_line;
+    let separator = if in_range { ":" } else { "-" };
+    let (head, symbol, tail) = split_highlight(line_num, text, range);
+    let symbol = if symbol.is_empty() {
+        symbol
+    } else {
+        symbol.color(color).bold().to_string()
+    };
+
+    format!(
+        "  {0: >6}{1} {2}{3}{4}",
+        line_num + 1,
+        separator,
+        head,
+        symbol,
+        tail
+    )
+}
+
+pub fn print_context(path: &Path, range: &SymbolRange, context: &ContextLines, color: Color) {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(_err) => return,
+    };
+
+    for (line_num, text) in get_context_window(&source, range, context) {
+        println!("{}", get_context_line_str(line_num, &text, range, color));
+    }
+}
+
+#[cfg(test)]
+mod source_context_tests {
+
+    use super::*;
+
+    const SOURCE: &str = "fn one() {}\nfn two() {}\nfn three() {}\nfn four() {}\nfn five() {}";
+
+    #[test]
+    fn context_window_includes_surrounding_lines() {
+        let range = SymbolRange::new(2, 3, 2, 8);
+        let window = get_context_window(SOURCE, &range, &ContextLines::new(1, 1));
+
+        assert_eq!(3, window.len());
+        assert_eq!(1, window[0].0);
+        assert_eq!("fn four() {}", window[2].1);
+    }
+
+    #[test]
+    fn context_window_is_clamped_to_file() {
+        let range = SymbolRange::new(0, 3, 0, 6);
+        let window = get_context_window(SOURCE, &range, &ContextLines::new(5, 10));
+
+        assert_eq!(5, window.len());
+        assert_eq!(0, window[0].0);
+    }
+
+    #[test]
+    fn highlight_splits_on_symbol_range() {
+        let range = SymbolRange::new(2, 3, 2, 8);
+        let (head, symbol, tail) = split_highlight(2, "fn three() {}", &range);
+
+        assert_eq!("fn ", head);
+        assert_eq!("three", symbol);
+        assert_eq!("() {}", tail);
+    }
+
+    #[test]
+    fn context_lines_are_counted_from_one() {
+        let range = SymbolRange::new(2, 3, 2, 8);
+
+        assert!(get_context_line_str(2, "fn three() {}", &range, Color::Red)
+            .starts_with("       3: fn "));
+        assert_eq!(
+            "       2- fn two() {}",
+            get_context_line_str(1, "fn two() {}", &range, Color::Red)
+        );
+    }
+
+    #[test]
+    fn highlight_skips_lines_outside_range() {
+        let range = SymbolRange::new(2, 3, 2, 8);
+        let (head, symbol, _) = split_highlight(1, "fn two() {}", &range);
+
+        assert_eq!("fn two() {}", head);
+        assert!(symbol.is_empty());
+    }
+
+    #[test]
+    fn empty_context_is_detected() {
+        assert!(ContextLines::new(0, 0).is_empty());
+        assert!(!ContextLines::new(0, 2).is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+use colored::*;
+
+const ELLIPSIS: char = '…';
+
+// Characters taken up by the "| " prefix and the " | " between columns
+const ROW_PREFIX_WIDTH: usize = 2;
+const COLUMN_SEPARATOR_WIDTH: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Elide {
+    Never,
+    End,
+    Middle,
+}
+
+pub struct Column {
+    heading: String,
+    elide: Elide,
+    min_width: usize,
+}
+
+impl Column {
+    pub fn new(heading: &str, elide: Elide, min_width: usize) -> Column {
+        Column {
+            heading: heading.to_string(),
+            elide,
+            min_width,
+        }
+    }
+}
+
+pub struct Cell {
+    text: String,
+    color: Option<Color>,
+}
+
+impl Cell {
+    pub fn new(text: String) -> Cell {
+        Cell { text, color: None }
+    }
+
+    pub fn colored(text: String, color: Option<Color>) -> Cell {
+        Cell { text, color }
+    }
+}
+
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<Column>) -> Table {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push_row(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    // Widest content (or heading) of every column
+    fn get_natural_widths(&self) -> Vec<usize> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                self.rows
+                    .iter()
+                    .map(|row| display_width(&row[i].text))
+                    .chain(std::iter::once(display_width(&column.heading)))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    // Fits the columns into max_width by shrinking the widest elidable column first
+    pub fn get_column_widths(&self, max_width: Option<usize>) -> Vec<usize> {
+        let mut widths = self.get_natural_widths();
+        let max_width = match max_width {
+            Some(width) => width,
+            None => return widths,
+        };
+
+        let overhead = ROW_PREFIX_WIDTH + COLUMN_SEPARATOR_WIDTH * (widths.len().saturating_sub(1));
+        while widths.iter().sum::<usize>() + overhead > max_width {
+            let widest = widths
+                .iter()
+                .enumerate()
+                .filter(|(i, width)| {
+                    self.columns[*i].elide != Elide::Never && **width > self.columns[*i].min_width
+                })
+                .max_by_key(|(_, width)| **width)
+                .map(|(i, _)| i);
+
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+
+        widths
+    }
+
+    pub fn get_heading_str(&self, widths: &[usize], color: Color) -> String {
+        let cells: Vec<Cell> = self
+            .columns
+            .iter()
+            .map(|column| Cell::colored(column.heading.clone(), Some(color)))
+            .collect();
+
+        format!("| {}", self.fit_cells(&cells, widths, true).join(" | "))
+    }
+
+    pub fn get_row_str(&self, index: usize, widths: &[usize]) -> String {
+        format!(
+            "| {}",
+            self.fit_cells(&self.rows[index], widths, false).join(" | ")
+        )
+    }
+
+    // Elides then pads every cell to its column width, leaving the last column unpadded
+    fn fit_cells(&self, cells: &[Cell], widths: &[usize], bold: bool) -> Vec<String> {
+        let last = cells.len().saturating_sub(1);
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                let text = elide(&cell.text, widths[i], self.columns[i].elide);
+                let fill = if i == last {
+                    String::new()
+                } else {
+                    " ".repeat(widths[i].saturating_sub(display_width(&text)))
+                };
+
+                match cell.color {
+                    Some(color) if bold => format!("{}{}", text.color(color).bold(), fill),
+                    Some(color) => format!("{}{}", text.color(color), fill),
+                    None => text + &fill,
+                }
+            })
+            .collect()
+    }
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars().count()
+}
+
+pub fn elide(text: &str, width: usize, mode: Elide) -> String {
+    let len = display_width(text);
+    if len <= width || mode == Elide::Never {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    match mode {
+        Elide::End => {
+            let mut kept: String = chars[..width - 1].iter().collect();
+            kept.push(ELLIPSIS);
+            kept
+        }
+        Elide::Middle => {
+            // favour the tail, it holds the file name
+            let keep = width - 1;
+            let head = keep / 2;
+            let tail = keep - head;
+            let mut kept: String = chars[..head].iter().collect();
+            kept.push(ELLIPSIS);
+            kept.extend(chars[len - tail..].iter());
+            kept
+        }
+        Elide::Never => text.to_string(),
+    }
+}
+
+#[cfg(unix)]
+pub fn get_terminal_width() -> Option<usize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+
+    if ok && size.ws_col > 0 {
+        Some(size.ws_col as usize)
+    } else {
+        get_columns_env()
+    }
+}
+
+#[cfg(not(unix))]
+pub fn get_terminal_width() -> Option<usize> {
+    get_columns_env()
+}
+
+fn get_columns_env() -> Option<usize> {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .filter(|columns| *columns > 0)
+}
+
+#[cfg(test)]
+mod table_tests {
+
+    use super::*;
+
+    fn get_test_table() -> Table {
+        let mut table = Table::new(vec![
+            Column::new("Name", Elide::End, 4),
+            Column::new("Line", Elide::Never, 4),
+            Column::new("Location", Elide::Middle, 8),
+        ]);
+        table.push_row(vec![
+            Cell::new("a_rather_long_function_name".to_string()),
+            Cell::colored("12".to_string(), Some(Color::Blue)),
+            Cell::new("/home/user/project/src/net/http/client.rs".to_string()),
+        ]);
+        table
+    }
+
+    #[test]
+    fn columns_size_to_content() {
+        let widths = get_test_table().get_column_widths(None);
+        assert_eq!(vec![27, 4, 41], widths);
+    }
+
+    #[test]
+    fn columns_shrink_to_fit_width() {
+        let table = get_test_table();
+        let widths = table.get_column_widths(Some(60));
+
+        assert!(widths.iter().sum::<usize>() + 8 <= 60);
+        assert_eq!(4, widths[1]);
+        assert!(display_width(&table.get_row_str(0, &widths)) <= 60);
+    }
+
+    #[test]
+    fn columns_never_shrink_below_minimum() {
+        let widths = get_test_table().get_column_widths(Some(10));
+        assert_eq!(vec![4, 4, 8], widths);
+    }
+
+    // Drops the colour escapes, so rows compare the same whether or not colour is on
+    fn strip_ansi(text: &str) -> String {
+        let mut stripped = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| *c == 'm');
+            } else {
+                stripped.push(c);
+            }
+        }
+        stripped
+    }
+
+    #[test]
+    fn colored_cells_keep_their_padding() {
+        let table = get_test_table();
+        let widths = table.get_column_widths(None);
+
+        let row = strip_ansi(&table.get_row_str(0, &widths));
+        assert!(row.contains("| 12   |"));
+    }
+
+    #[test]
+    fn ansi_escapes_are_stripped() {
+        assert_eq!("12", strip_ansi(&format!("{}", "12".color(Color::Blue))));
+        assert_eq!("12", strip_ansi("\x1b[1;34m12\x1b[0m"));
+    }
+
+    #[test]
+    fn elide_middle_keeps_both_ends() {
+        let elided = elide("/home/user/project/src/lib.rs", 15, Elide::Middle);
+
+        assert_eq!(15, display_width(&elided));
+        assert!(elided.starts_with("/home/u"));
+        assert!(elided.ends_with("/lib.rs"));
+    }
+
+    #[test]
+    fn elide_end_marks_truncation() {
+        assert_eq!("parse_…", elide("parse_arguments", 7, Elide::End));
+    }
+
+    #[test]
+    fn short_text_is_not_elided() {
+        assert_eq!("parse", elide("parse", 7, Elide::Middle));
+    }
+}
@@ -0,0 +1,325 @@
+use std::io::{self, Read};
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Esc,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+// The line being edited, with the cursor counted in characters
+#[derive(Default)]
+pub struct LineBuffer {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    pub fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // Applies an editing key, false for keys that don't edit the line
+    pub fn edit(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) => {
+                self.chars.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete | Key::Ctrl('d') if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.chars.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.chars.len(),
+            Key::Ctrl('u') => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('k') => self.chars.truncate(self.cursor),
+            _ => return false,
+        }
+        true
+    }
+}
+
+// Puts the terminal on stdin into raw mode, restoring it when dropped
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        // safe, termios is plain data that tcgetattr fills in
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        // safe, cfmakeraw only changes the flags of the struct it is given
+        unsafe { libc::cfmakeraw(&mut raw) };
+        // keep turning "\n" into "\r\n" so printed results still line up
+        raw.c_oflag |= libc::OPOST;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawMode { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // safe, restores the settings read in enable
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+// How long the rest of an escape sequence may take to arrive before ESC counts as a key
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(25);
+
+// Key presses are read from, with a way to tell whether more of a sequence is on its way
+pub trait KeyInput: Read {
+    fn is_pending(&mut self) -> bool;
+}
+
+// Reads stdin a byte at a time without buffering, so nothing is held back from poll
+pub struct RawInput;
+
+impl KeyInput for RawInput {
+    fn is_pending(&mut self) -> bool {
+        wait_for_input(ESCAPE_TIMEOUT).unwrap_or(false)
+    }
+}
+
+impl KeyInput for &[u8] {
+    fn is_pending(&mut self) -> bool {
+        !self.is_empty()
+    }
+}
+
+impl Read for RawInput {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        // safe, the kernel writes at most buffer.len() bytes into it
+        let read = unsafe {
+            libc::read(
+                libc::STDIN_FILENO,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(read as usize)
+    }
+}
+
+// Waits up to the timeout for a key press to read
+pub fn wait_for_input(timeout: Duration) -> io::Result<bool> {
+    let mut poll_fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // safe, poll_fd is a single valid pollfd for the length of the call
+    let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+    if ready < 0 {
+        let error = io::Error::last_os_error();
+        if error.kind() == io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(error);
+    }
+    Ok(ready > 0)
+}
+
+// Columns and rows of the terminal on stdout
+pub fn get_size() -> Option<(usize, usize)> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+
+    if ok && size.ws_col > 0 && size.ws_row > 0 {
+        Some((size.ws_col as usize, size.ws_row as usize))
+    } else {
+        None
+    }
+}
+
+fn read_byte(input: &mut dyn Read) -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// The rest of an escape sequence after ESC, e.g. "[A" for up or "[3~" for delete
+fn read_escape(input: &mut dyn KeyInput) -> io::Result<Key> {
+    // a lone ESC is the key itself, a sequence arrives all at once
+    if !input.is_pending() || !matches!(read_byte(input)?, Some(b'[') | Some(b'O')) {
+        return Ok(Key::Esc);
+    }
+
+    let mut code = String::new();
+    while let Some(byte) = read_byte(input)? {
+        if byte.is_ascii_digit() || byte == b';' {
+            code.push(byte as char);
+            continue;
+        }
+        return Ok(match (byte, code.as_str()) {
+            (b'A', _) => Key::Up,
+            (b'B', _) => Key::Down,
+            (b'C', _) => Key::Right,
+            (b'D', _) => Key::Left,
+            (b'H', _) => Key::Home,
+            (b'F', _) => Key::End,
+            (b'~', "1") | (b'~', "7") => Key::Home,
+            (b'~', "4") | (b'~', "8") => Key::End,
+            (b'~', "3") => Key::Delete,
+            (b'~', "5") => Key::PageUp,
+            (b'~', "6") => Key::PageDown,
+            _ => Key::Esc,
+        });
+    }
+    Ok(Key::Esc)
+}
+
+// Reads one key press, None once the input is closed
+pub fn read_key(input: &mut dyn KeyInput) -> io::Result<Option<Key>> {
+    let byte = match read_byte(input)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        127 | 8 => Key::Backspace,
+        27 => read_escape(input)?,
+        1..=26 => Key::Ctrl((b'a' + byte - 1) as char),
+        _ if byte < 128 => Key::Char(byte as char),
+        _ => {
+            // the leading byte says how many continuation bytes follow
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..len {
+                match read_byte(input)? {
+                    Some(next) => bytes.push(next),
+                    None => break,
+                }
+            }
+            let text = String::from_utf8_lossy(&bytes);
+            Key::Char(text.chars().next().unwrap_or('\u{fffd}'))
+        }
+    };
+
+    Ok(Some(key))
+}
+
+#[cfg(test)]
+mod terminal_tests {
+
+    use super::*;
+
+    fn read_keys(bytes: &[u8]) -> Vec<Key> {
+        let mut input = bytes;
+        let mut keys = Vec::new();
+        while let Some(key) = read_key(&mut input).unwrap() {
+            keys.push(key);
+        }
+        keys
+    }
+
+    #[test]
+    fn escape_sequences_are_read_as_keys() {
+        assert_eq!(
+            vec![
+                Key::Up,
+                Key::Left,
+                Key::Delete,
+                Key::End,
+                Key::PageDown,
+                Key::Esc
+            ],
+            read_keys(b"\x1b[A\x1bOD\x1b[3~\x1b[F\x1b[6~\x1b")
+        );
+    }
+
+    #[test]
+    fn control_and_unicode_keys_are_read() {
+        assert_eq!(
+            vec![
+                Key::Ctrl('c'),
+                Key::Char('é'),
+                Key::Backspace,
+                Key::Enter,
+                Key::Char('a')
+            ],
+            read_keys("\x03é\x7f\ra".as_bytes())
+        );
+    }
+
+    #[test]
+    fn line_buffer_edits_at_cursor() {
+        let mut buffer = LineBuffer::default();
+        for c in "pars".chars() {
+            buffer.edit(Key::Char(c));
+        }
+        buffer.edit(Key::Left);
+        buffer.edit(Key::Backspace);
+        buffer.edit(Key::End);
+        buffer.edit(Key::Char('e'));
+        assert_eq!("pas".to_string() + "e", buffer.text());
+
+        buffer.edit(Key::Home);
+        buffer.edit(Key::Ctrl('k'));
+        assert!(buffer.is_empty());
+        assert!(!buffer.edit(Key::Up));
+    }
+}
@@ -0,0 +1,181 @@
+use colored::Color;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn from_name(choice: &str) -> Option<ColorChoice> {
+        match choice {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+}
+
+fn is_env_enabled(value: &Option<String>) -> bool {
+    match value {
+        Some(value) => !value.is_empty() && value != "0",
+        None => false,
+    }
+}
+
+// CLICOLOR_FORCE takes priority over NO_COLOR, which takes priority over the tty check
+pub fn should_colorize(
+    choice: ColorChoice,
+    no_color: Option<String>,
+    clicolor_force: Option<String>,
+    is_tty: bool,
+) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            if is_env_enabled(&clicolor_force) {
+                true
+            } else if no_color.is_some_and(|value| !value.is_empty()) {
+                false
+            } else {
+                is_tty
+            }
+        }
+    }
+}
+
+pub fn apply_color_choice(choice: ColorChoice) {
+    let colorize = should_colorize(
+        choice,
+        std::env::var("NO_COLOR").ok(),
+        std::env::var("CLICOLOR_FORCE").ok(),
+        std::io::stdout().is_terminal(),
+    );
+    colored::control::set_override(colorize);
+}
+
+pub struct Theme {
+    pub heading: Color,
+    pub highlight: Color,
+    kinds: HashMap<String, Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        let mut kinds = HashMap::new();
+        kinds.insert("function".to_string(), Color::Blue);
+        kinds.insert("method".to_string(), Color::Cyan);
+        kinds.insert("constructor".to_string(), Color::Cyan);
+        kinds.insert("struct".to_string(), Color::Yellow);
+        kinds.insert("class".to_string(), Color::Yellow);
+        kinds.insert("interface".to_string(), Color::Yellow);
+        kinds.insert("enum".to_string(), Color::Magenta);
+        kinds.insert("enummember".to_string(), Color::Magenta);
+        kinds.insert("module".to_string(), Color::Green);
+        kinds.insert("constant".to_string(), Color::Red);
+
+        Theme {
+            heading: Color::Green,
+            highlight: Color::Red,
+            kinds,
+        }
+    }
+}
+
+impl Theme {
+    // Applies "kind=color" overrides separated by ':', e.g. "function=red:heading=blue"
+    pub fn from_spec(spec: &str) -> Theme {
+        let mut theme = Theme::default();
+
+        for entry in spec.split(':') {
+            let mut parts = entry.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim().to_lowercase();
+            let color = match parts.next().map(|color| color.trim().parse::<Color>()) {
+                Some(Ok(color)) => color,
+                _ => continue,
+            };
+
+            match key.as_ref() {
+                "heading" => theme.heading = color,
+                "match" => theme.highlight = color,
+                "" => continue,
+                _ => {
+                    theme.kinds.insert(key, color);
+                }
+            }
+        }
+
+        theme
+    }
+
+    pub fn from_env() -> Theme {
+        match std::env::var("GLSP_COLORS") {
+            Ok(spec) => Theme::from_spec(&spec),
+            Err(_err) => Theme::default(),
+        }
+    }
+
+    pub fn kind_color(&self, kind: &str) -> Option<Color> {
+        self.kinds.get(&kind.to_lowercase()).cloned()
+    }
+}
+
+#[cfg(test)]
+mod theme_tests {
+
+    use super::*;
+
+    #[test]
+    fn explicit_choice_ignores_environment() {
+        let no_color = Some("1".to_string());
+        assert!(should_colorize(ColorChoice::Always, no_color, None, false));
+        assert!(!should_colorize(ColorChoice::Never, None, None, true));
+    }
+
+    #[test]
+    fn auto_follows_tty() {
+        assert!(should_colorize(ColorChoice::Auto, None, None, true));
+        assert!(!should_colorize(ColorChoice::Auto, None, None, false));
+    }
+
+    #[test]
+    fn auto_respects_no_color() {
+        let no_color = Some("1".to_string());
+        assert!(!should_colorize(ColorChoice::Auto, no_color, None, true));
+    }
+
+    #[test]
+    fn clicolor_force_beats_no_color() {
+        let no_color = Some("1".to_string());
+        let force = Some("1".to_string());
+        assert!(should_colorize(ColorChoice::Auto, no_color, force, false));
+
+        let force_off = Some("0".to_string());
+        assert!(!should_colorize(ColorChoice::Auto, None, force_off, false));
+    }
+
+    #[test]
+    fn spec_overrides_defaults() {
+        let theme = Theme::from_spec("function=red:heading=blue:bogus");
+
+        assert_eq!(Some(Color::Red), theme.kind_color("Function"));
+        assert_eq!(Color::Blue, theme.heading);
+        assert_eq!(Some(Color::Magenta), theme.kind_color("Enum"));
+    }
+
+    #[test]
+    fn unknown_colors_are_ignored() {
+        let theme = Theme::from_spec("struct=notacolor");
+        assert_eq!(Some(Color::Yellow), theme.kind_color("Struct"));
+    }
+
+    #[test]
+    fn uncolored_kinds_have_no_color() {
+        assert_eq!(None, Theme::default().kind_color("Variable"));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::lsp_message::FileChange;
+use ignore::WalkBuilder;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_MODIFY
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO;
+
+// wd, mask, cookie and len come before the name of every inotify_event
+const EVENT_HEADER_SIZE: usize = 16;
+
+// Watches every directory of a project with inotify for changes to its source files
+pub struct Watcher {
+    fd: libc::c_int,
+    dirs: HashMap<libc::c_int, PathBuf>,
+    extensions: &'static [&'static str],
+}
+
+fn read_i32(buffer: &[u8], offset: usize) -> i32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buffer[offset..offset + 4]);
+    i32::from_ne_bytes(bytes)
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buffer[offset..offset + 4]);
+    u32::from_ne_bytes(bytes)
+}
+
+fn get_change(mask: u32) -> Option<FileChange> {
+    if mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+        Some(FileChange::Created)
+    } else if mask & (libc::IN_MODIFY | libc::IN_CLOSE_WRITE) != 0 {
+        Some(FileChange::Changed)
+    } else if mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+        Some(FileChange::Deleted)
+    } else {
+        None
+    }
+}
+
+// A file written right after it was created is still new to the server
+fn merge_change(changes: &mut BTreeMap<PathBuf, FileChange>, path: PathBuf, change: FileChange) {
+    let merged = match (changes.get(&path), change) {
+        (Some(FileChange::Created), FileChange::Changed) => FileChange::Created,
+        _ => change,
+    };
+    changes.insert(path, merged);
+}
+
+impl Watcher {
+    pub fn new(root: &Path, extensions: &'static [&'static str]) -> io::Result<Watcher> {
+        // safe, inotify_init1 only takes flags
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut watcher = Watcher {
+            fd,
+            dirs: HashMap::new(),
+            extensions,
+        };
+        watcher.add_tree(root)?;
+        Ok(watcher)
+    }
+
+    // Directories skipped by the ignore files are not watched, like they are not indexed
+    fn add_tree(&mut self, dir: &Path) -> io::Result<()> {
+        let dirs: Vec<PathBuf> = WalkBuilder::new(dir)
+            .require_git(false)
+            .add_custom_ignore_filename(".glspignore")
+            .build()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_dir()))
+            .map(|entry| entry.into_path())
+            .collect();
+
+        for dir in dirs {
+            self.add_dir(&dir)?;
+        }
+        Ok(())
+    }
+
+    fn add_dir(&mut self, dir: &Path) -> io::Result<()> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        // safe, the path is a valid C string that outlives the call
+        let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.dirs.insert(wd, dir.to_path_buf());
+        Ok(())
+    }
+
+    fn is_source_file(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| self.extensions.contains(&extension))
+    }
+
+    // Waits up to the timeout, or forever without one, for events to read
+    fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |timeout| timeout.as_millis() as libc::c_int);
+        loop {
+            // safe, poll_fd is a single valid pollfd for the length of the call
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout) };
+            if ready >= 0 {
+                return Ok(ready > 0);
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+
+    fn read_events(&mut self, changes: &mut BTreeMap<PathBuf, FileChange>) -> io::Result<()> {
+        let mut buffer = [0u8; 4096];
+        // safe, the kernel writes at most buffer.len() bytes into it
+        let read = unsafe {
+            libc::read(
+                self.fd,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let read = read as usize;
+        let mut offset = 0;
+        while offset + EVENT_HEADER_SIZE <= read {
+            let wd = read_i32(&buffer, offset);
+            let mask = read_u32(&buffer, offset + 4);
+            let len = read_u32(&buffer, offset + 12) as usize;
+            let name = &buffer[offset + EVENT_HEADER_SIZE..offset + EVENT_HEADER_SIZE + len];
+            offset += EVENT_HEADER_SIZE + len;
+
+            if mask & libc::IN_IGNORED != 0 {
+                self.dirs.remove(&wd);
+                continue;
+            }
+            let dir = match self.dirs.get(&wd) {
+                Some(dir) => dir,
+                None => continue,
+            };
+            // the name is padded with nul bytes up to the event's length
+            let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            let path = dir.join(OsStr::from_bytes(&name[..end]));
+
+            if mask & libc::IN_ISDIR != 0 {
+                if mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                    self.add_tree(&path)?;
+                }
+            } else if self.is_source_file(&path) {
+                if let Some(change) = get_change(mask) {
+                    merge_change(changes, path, change);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Blocks until a source file changes, then keeps collecting until nothing has happened for
+    // the debounce, so saving several files at once is reported together. Returns no changes
+    // once the idle timeout passes without any
+    pub fn wait(
+        &mut self,
+        debounce: Duration,
+        idle: Option<Duration>,
+    ) -> io::Result<Vec<(PathBuf, FileChange)>> {
+        let mut changes: BTreeMap<PathBuf, FileChange> = BTreeMap::new();
+        loop {
+            let timeout = if changes.is_empty() {
+                idle
+            } else {
+                Some(debounce)
+            };
+            if self.poll(timeout)? {
+                self.read_events(&mut changes)?;
+            } else {
+                return Ok(changes.into_iter().collect());
+            }
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // safe, the descriptor is owned by the watcher and closed once
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod watcher_tests {
+
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn created_then_written_file_stays_created() {
+        let mut changes = BTreeMap::new();
+        merge_change(&mut changes, PathBuf::from("a.rs"), FileChange::Created);
+        merge_change(&mut changes, PathBuf::from("a.rs"), FileChange::Changed);
+        merge_change(&mut changes, PathBuf::from("b.rs"), FileChange::Changed);
+        merge_change(&mut changes, PathBuf::from("b.rs"), FileChange::Deleted);
+
+        assert_eq!(Some(&FileChange::Created), changes.get(Path::new("a.rs")));
+        assert_eq!(Some(&FileChange::Deleted), changes.get(Path::new("b.rs")));
+    }
+
+    #[test]
+    fn source_file_changes_are_reported() {
+        let root = std::env::temp_dir().join(format!("glsp-watcher-{}", std::process::id()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        let mut watcher = Watcher::new(&root, &["rs"]).unwrap();
+
+        fs::write(root.join("README.md"), "# readme").unwrap();
+        fs::create_dir_all(root.join("src").join("net")).unwrap();
+        fs::write(root.join("src").join("lib.rs"), "fn lib() {}").unwrap();
+        let changes = watcher.wait(Duration::from_millis(50), None).unwrap();
+
+        assert_eq!(
+            vec![(root.join("src").join("lib.rs"), FileChange::Created)],
+            changes
+        );
+        let idle = Some(Duration::from_millis(50));
+        assert!(watcher
+            .wait(Duration::from_millis(50), idle)
+            .unwrap()
+            .is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+}